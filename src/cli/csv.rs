@@ -1,44 +1,621 @@
-use std::{fmt, str::FromStr};
+use std::{fmt, path::PathBuf, str::FromStr};
 
-use clap::Parser;
+use clap::{ArgAction, ArgGroup, Args, Parser};
+use encoding_rs::Encoding;
+use enum_dispatch::enum_dispatch;
+use tokio::fs;
 
-use crate::CmdExecutor;
+use crate::{CmdExecutor, WriterConfig};
 
-use super::verify_file;
+use super::{verify_file, verify_path};
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum OutputFormat {
     Json,
     Yaml,
+    Ndjson,
+    Csv,
+    Toml,
+    Xml,
+    Markdown,
+    Html,
+    Xlsx,
+    Parquet,
+    Arrow,
+}
+
+#[derive(Debug, Parser)]
+#[command(args_conflicts_with_subcommands = true)]
+pub struct CsvCommand {
+    #[command(subcommand)]
+    pub cmd: Option<CsvSubCommand>,
+
+    // 没有子命令时就是原来的rcli csv -i xxx.csv转换
+    // 这里不能用Option<CsvOpts>，clap不会为嵌套flatten的结构体生成group，结果永远是None
+    #[command(flatten)]
+    pub convert: CsvOpts,
+}
+
+#[derive(Debug, Parser)]
+#[enum_dispatch(CmdExecutor)]
+pub enum CsvSubCommand {
+    #[command(about = "Select, filter and sort CSV rows with a SQL-like query")]
+    Query(CsvQueryOpts),
+    #[command(about = "Infer a JSON Schema from a CSV file")]
+    Schema(CsvSchemaOpts),
+    #[command(about = "Validate a CSV file against a JSON Schema")]
+    Validate(CsvValidateOpts),
+    #[command(about = "Show per-column statistics of a CSV file")]
+    Describe(CsvDescribeOpts),
+    #[command(about = "Join two CSV files on one or more key columns")]
+    Join(CsvJoinOpts),
+    #[command(about = "Remove rows with duplicate keys")]
+    Dedupe(CsvDedupeOpts),
+    #[command(about = "Sort CSV rows by one or more columns, spilling to disk for big files")]
+    Sort(CsvSortOpts),
+    #[command(about = "Show added, removed and modified rows between two versions of a file")]
+    Diff(CsvDiffOpts),
+    #[command(about = "Split a file into parts by row count, size or the value of a column")]
+    Split(CsvSplitOpts),
+    #[command(about = "Concatenate files, aligning their columns by name")]
+    Cat(CsvCatOpts),
+    #[command(about = "Pick a random sample of rows")]
+    Sample(CsvSampleOpts),
 }
 
 #[derive(Debug, Parser)]
 pub struct CsvOpts {
-    #[arg(short, long, value_parser = verify_file)]
+    #[command(flatten)]
+    pub source: CsvInputOpts,
+
+    #[command(flatten)]
+    pub target: CsvOutputOpts,
+
+    /// column order of the csv output, defaults to the union of keys in first-seen order
+    #[arg(long, value_delimiter = ',')]
+    pub columns: Vec<String>,
+
+    /// keep only these columns, in this order
+    #[arg(long, value_delimiter = ',')]
+    pub select: Vec<String>,
+
+    /// rename columns, e.g. "Kit Number=kit,Name=name"
+    #[arg(long, value_parser = parse_rename, value_delimiter = ',')]
+    pub rename: Vec<Rename>,
+
+    /// yaml file that picks, renames and derives columns, applied before --select and --rename
+    #[arg(long, value_parser = verify_file)]
+    pub mapping: Option<String>,
+
+    /// build nested objects and arrays from headers like "address.city" and "tags[0]",
+    /// or flatten nested documents into such headers when converting back to csv
+    #[arg(long, default_value_t = false)]
+    pub nested: bool,
+
+    /// print the file as a table on stdout instead of writing the output file
+    #[arg(short, long, default_value_t = false)]
+    pub show: bool,
+
+    /// only show the first N rows
+    #[arg(long, conflicts_with = "tail", requires = "show")]
+    pub head: Option<usize>,
+
+    /// only show the last N rows
+    #[arg(long, requires = "show")]
+    pub tail: Option<usize>,
+
+    /// cells wider than this are truncated when showing the table
+    #[arg(long, default_value_t = 40, requires = "show")]
+    pub max_width: usize,
+
+    /// convert csv input on this many threads, 0 means one per cpu
+    #[arg(long, default_value_t = 1)]
+    pub threads: usize,
+
+    /// what to do with malformed rows: fail, skip, or report them to --reject-file
+    #[arg(long, value_parser = parse_on_error, default_value = "fail")]
+    pub on_error: OnError,
+
+    /// where --on-error report writes the rejected rows, defaults to <output>.rejected.csv.
+    /// required when the output is stdout
+    #[arg(long)]
+    pub reject_file: Option<String>,
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvQueryOpts {
+    #[command(flatten)]
+    pub source: CsvInputOpts,
+
+    #[command(flatten)]
+    pub target: CsvOutputOpts,
+
+    /// e.g. "SELECT Name, \"Kit Number\" WHERE Position = 'Goalkeeper' ORDER BY \"Kit Number\" LIMIT 5"
+    pub query: String,
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvSchemaOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
     pub input: String,
 
+    #[command(flatten)]
+    pub dialect: CsvDialectOpts,
+
+    /// write the schema to this file instead of stdout
+    #[arg(short, long)]
+    pub output: Option<String>,
+
+    /// columns with at most this many distinct values become enums
+    #[arg(long, default_value_t = 10)]
+    pub max_enum: usize,
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvValidateOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+
+    #[command(flatten)]
+    pub dialect: CsvDialectOpts,
+
+    /// JSON Schema file, in json or yaml
+    #[arg(long, value_parser = verify_file)]
+    pub schema: String,
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvDescribeOpts {
+    #[command(flatten)]
+    pub source: CsvInputOpts,
+
+    /// number of most frequent values to show per column
+    #[arg(long, default_value_t = 5)]
+    pub top: usize,
+
+    /// count distinct values exactly up to this many, then estimate with HyperLogLog
+    #[arg(long, default_value_t = 10000)]
+    pub distinct_limit: usize,
+
+    /// print the statistics as JSON instead of a table
+    #[arg(long, default_value_t = false)]
+    pub json: bool,
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvJoinOpts {
+    /// the left side is --input
+    #[command(flatten)]
+    pub source: CsvInputOpts,
+
+    #[command(flatten)]
+    pub target: CsvOutputOpts,
+
+    /// the right side, read with the same options as --input
+    #[arg(long, value_parser = verify_file)]
+    pub right: String,
+
+    /// key columns, must exist in both files
+    #[arg(long, value_delimiter = ',', required = true)]
+    pub on: Vec<String>,
+
+    #[arg(long, value_parser = parse_join_kind, default_value = "inner")]
+    pub how: JoinKind,
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvDedupeOpts {
+    #[command(flatten)]
+    pub source: CsvInputOpts,
+
+    #[command(flatten)]
+    pub target: CsvOutputOpts,
+
+    /// key columns, defaults to the whole row
+    #[arg(long, value_delimiter = ',')]
+    pub key: Vec<String>,
+
+    /// which of the duplicate rows to keep
+    #[arg(long, value_parser = parse_keep, default_value = "first")]
+    pub keep: Keep,
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvSortOpts {
+    #[command(flatten)]
+    pub source: CsvInputOpts,
+
+    #[command(flatten)]
+    pub target: CsvOutputOpts,
+
+    /// sort keys, e.g. "Position,Kit Number:desc"
+    #[arg(long, value_parser = parse_sort_key, value_delimiter = ',', required = true)]
+    pub by: Vec<SortKey>,
+
+    /// rows sorted in memory at a time, bigger inputs are sorted in chunks on disk and merged
+    #[arg(long, default_value_t = 100_000)]
+    pub chunk_rows: usize,
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvDiffOpts {
+    #[arg(value_parser = verify_file)]
+    pub old: String,
+
+    #[arg(value_parser = verify_file)]
+    pub new: String,
+
+    #[command(flatten)]
+    pub dialect: CsvDialectOpts,
+
+    /// key columns that identify a row in both files
+    #[arg(long, value_delimiter = ',', required = true)]
+    pub key: Vec<String>,
+
+    /// text, json, or csv for a patch file
+    #[arg(long, value_parser = parse_diff_format, default_value = "text")]
+    pub format: DiffFormat,
+
+    #[arg(short, long)]
+    pub output: Option<String>,
+}
+
+#[derive(Debug, Parser)]
+#[command(group(ArgGroup::new("split_by").required(true).args(["rows", "bytes", "by"])))]
+pub struct CsvSplitOpts {
+    #[command(flatten)]
+    pub source: CsvInputOpts,
+
+    /// rows per part
+    #[arg(long)]
+    pub rows: Option<usize>,
+
+    /// maximum size of a part counted as csv, e.g. 512K, 10MB, 1G
+    #[arg(long, value_parser = parse_size)]
+    pub bytes: Option<u64>,
+
+    /// one part per distinct value of this column
+    #[arg(long)]
+    pub by: Option<String>,
+
+    /// directory the parts are written to
+    #[arg(long, value_parser = verify_path, default_value = ".")]
+    pub out_dir: PathBuf,
+
+    /// file name prefix of the parts, defaults to the name of the input file
+    #[arg(long)]
+    pub prefix: Option<String>,
+
+    #[arg(long, value_parser = parse_format, default_value = "csv")]
+    pub format: OutputFormat,
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvCatOpts {
+    /// files to concatenate, all read with the same options
+    #[arg(value_parser = verify_file, required = true)]
+    pub inputs: Vec<String>,
+
+    #[command(flatten)]
+    pub dialect: CsvDialectOpts,
+
+    /// emit numbers, booleans and nulls instead of strings where the cell is unambiguous
+    #[arg(long, default_value_t = false)]
+    pub infer: bool,
+
+    /// force column types, e.g. "Kit Number=int,DOB=date"
+    #[arg(long = "type", value_parser = parse_column_type_override, value_delimiter = ',')]
+    pub types: Vec<ColumnTypeOverride>,
+
+    #[command(flatten)]
+    pub target: CsvOutputOpts,
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvSampleOpts {
+    #[command(flatten)]
+    pub source: CsvInputOpts,
+
+    #[command(flatten)]
+    pub target: CsvOutputOpts,
+
+    /// number of rows to pick
+    #[arg(short = 'n', long, default_value_t = 10)]
+    pub size: usize,
+
+    /// seed of the random generator, the same seed picks the same rows
+    #[arg(long)]
+    pub seed: Option<u64>,
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct CsvInputOpts {
+    /// input file, "-" for stdin. .gz, .zst and .bz2 files are decompressed
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+
+    /// format of the input file, guessed from its extension when not given
+    #[arg(long, value_parser = parse_format)]
+    pub input_format: Option<OutputFormat>,
+
+    #[command(flatten)]
+    pub dialect: CsvDialectOpts,
+
+    /// emit numbers, booleans and nulls instead of strings where the cell is unambiguous
+    #[arg(long, default_value_t = false)]
+    pub infer: bool,
+
+    /// force column types, e.g. "Kit Number=int,DOB=date"
+    #[arg(long = "type", value_parser = parse_column_type_override, value_delimiter = ',')]
+    pub types: Vec<ColumnTypeOverride>,
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct CsvOutputOpts {
     //default_value会自动为实现了From trait的做into转换, "output.json"是&str类型，而我们需要String类型，所以要default_value。"output.json".into()
+    /// output file, "-" for stdout. .gz, .zst and .bz2 files are compressed
     #[arg(short, long)]
     pub output: Option<String>,
 
     #[arg(long, value_parser = parse_format, default_value = "json")]
     pub format: OutputFormat,
 
-    #[arg(short, long, default_value = ",")]
-    pub delimiter: char,
+    /// name of the root element of xml output
+    #[arg(long, default_value = "rows")]
+    pub root_name: String,
+
+    /// name of the row element of xml output, and of the array of tables of toml output
+    #[arg(long, default_value = "row")]
+    pub row_name: String,
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct CsvDialectOpts {
+    /// field delimiter, a single character, "tab", or "auto" to sniff it from the file
+    #[arg(short, long, value_parser = parse_delimiter, default_value = ",")]
+    pub delimiter: CsvDelimiter,
+
+    /// whether the first row is a header, sniffed when --delimiter is auto and this is not given
+    #[arg(short = 'r', long, action = ArgAction::Set, num_args = 0..=1, default_missing_value = "true")]
+    pub header: Option<bool>,
+
+    /// input encoding, e.g. gbk, gb18030, shift_jis, latin1, or "auto" to detect it
+    #[arg(short, long, value_parser = parse_encoding, default_value = "auto")]
+    pub encoding: CsvEncoding,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnType {
+    String,
+    Int,
+    Float,
+    Bool,
+    Date,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColumnTypeOverride {
+    pub column: String,
+    pub ty: ColumnType,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JoinKind {
+    Inner,
+    Left,
+    Outer,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Keep {
+    First,
+    Last,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rename {
+    pub from: String,
+    pub to: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnError {
+    Fail,
+    Skip,
+    Report,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffFormat {
+    Text,
+    Json,
+    Csv,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SplitBy {
+    Rows(usize),
+    Bytes(u64),
+    Column(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SortKey {
+    pub column: String,
+    pub desc: bool,
+}
 
-    #[arg(short = 'r', long, default_value_t = true)]
-    pub header: bool,
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CsvEncoding {
+    Auto,
+    Label(&'static Encoding),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CsvDelimiter {
+    Auto,
+    Char(u8),
+}
+
+impl CmdExecutor for CsvCommand {
+    async fn execute(self) -> anyhow::Result<()> {
+        match self.cmd {
+            Some(cmd) => cmd.execute().await,
+            None => self.convert.execute().await,
+        }
+    }
 }
 
 impl CmdExecutor for CsvOpts {
     async fn execute(self) -> anyhow::Result<()> {
-        let output = if let Some(output) = self.output {
-            output
+        if self.show {
+            return crate::process_csv_show(&self);
+        }
+        let output = if let Some(output) = &self.target.output {
+            output.clone()
         } else {
-            format!("output.{}", self.format)
+            format!("output.{}", self.target.format)
+        };
+        crate::process_csv(&self, &output)
+    }
+}
+
+impl CmdExecutor for CsvQueryOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        crate::process_csv_query(&self.source, &self.target, &self.query)
+    }
+}
+
+impl CmdExecutor for CsvSchemaOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let schema = crate::process_csv_schema(&self.input, &self.dialect, self.max_enum)?;
+        let schema = serde_json::to_string_pretty(&schema)?;
+        match self.output {
+            Some(output) => fs::write(output, schema).await?,
+            None => println!("{}", schema),
+        }
+        Ok(())
+    }
+}
+
+impl CmdExecutor for CsvValidateOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let violations = crate::process_csv_validate(&self.input, &self.dialect, &self.schema)?;
+        for v in &violations {
+            println!("line {}, column {}: {}", v.line, v.column, v.message);
+        }
+        if violations.is_empty() {
+            println!("valid");
+            Ok(())
+        } else {
+            Err(anyhow::anyhow!("{} violations found", violations.len()))
+        }
+    }
+}
+
+impl CmdExecutor for CsvDescribeOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        crate::process_csv_describe(&self.source, self.distinct_limit, self.top, self.json)
+    }
+}
+
+impl CmdExecutor for CsvJoinOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        crate::process_csv_join(&self.source, &self.right, &self.on, self.how, &self.target)
+    }
+}
+
+impl CmdExecutor for CsvDedupeOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        crate::process_csv_dedupe(&self.source, &self.key, self.keep, &self.target)
+    }
+}
+
+impl CmdExecutor for CsvSortOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        crate::process_csv_sort(&self.source, &self.by, self.chunk_rows, &self.target)
+    }
+}
+
+impl CmdExecutor for CsvDiffOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        crate::process_csv_diff(
+            &self.old,
+            &self.new,
+            &self.dialect,
+            &self.key,
+            self.format,
+            self.output.as_deref(),
+        )
+    }
+}
+
+impl CmdExecutor for CsvSplitOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let by = match (self.rows, self.bytes, self.by) {
+            (Some(rows), _, _) => SplitBy::Rows(rows),
+            (_, Some(bytes), _) => SplitBy::Bytes(bytes),
+            (_, _, Some(column)) => SplitBy::Column(column),
+            _ => anyhow::bail!("one of --rows, --bytes and --by is required"),
         };
-        crate::process_csv(&self.input, output, self.format)
+        crate::process_csv_split(
+            &self.source,
+            &by,
+            &self.out_dir,
+            self.prefix.as_deref(),
+            self.format,
+        )
+    }
+}
+
+impl CmdExecutor for CsvCatOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let sources: Vec<CsvInputOpts> = self
+            .inputs
+            .iter()
+            .map(|input| CsvInputOpts {
+                input: input.clone(),
+                input_format: None,
+                dialect: self.dialect.clone(),
+                infer: self.infer,
+                types: self.types.clone(),
+            })
+            .collect();
+        crate::process_csv_cat(&sources, &self.target)
+    }
+}
+
+impl CmdExecutor for CsvSampleOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        crate::process_csv_sample(&self.source, self.size, self.seed, &self.target)
+    }
+}
+
+impl CsvInputOpts {
+    pub fn input_format(&self) -> OutputFormat {
+        self.input_format
+            .unwrap_or_else(|| OutputFormat::from_path(&self.input))
+    }
+}
+
+impl CsvOutputOpts {
+    /// csv output uses the same delimiter as the input unless it was sniffed
+    pub fn writer_config(&self, dialect: &CsvDialectOpts) -> WriterConfig {
+        WriterConfig {
+            delimiter: dialect.output_delimiter(),
+            root_name: self.root_name.clone(),
+            row_name: self.row_name.clone(),
+        }
+    }
+}
+
+impl CsvDialectOpts {
+    /// the delimiter of csv output, a comma when the input delimiter is sniffed
+    pub fn output_delimiter(&self) -> u8 {
+        match self.delimiter {
+            CsvDelimiter::Char(c) => c,
+            CsvDelimiter::Auto => b',',
+        }
     }
 }
 
@@ -46,11 +623,79 @@ fn parse_format(format: &str) -> Result<OutputFormat, anyhow::Error> {
     format.parse()
 }
 
+fn parse_column_type_override(s: &str) -> Result<ColumnTypeOverride, anyhow::Error> {
+    s.parse()
+}
+
+fn parse_join_kind(how: &str) -> Result<JoinKind, anyhow::Error> {
+    how.parse()
+}
+
+fn parse_keep(keep: &str) -> Result<Keep, anyhow::Error> {
+    keep.parse()
+}
+
+// 1024进制，K/KB/KiB都一样
+fn parse_size(size: &str) -> Result<u64, anyhow::Error> {
+    let size = size.trim();
+    let split = size
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(size.len());
+    let (number, unit) = size.split_at(split);
+    let number: u64 = number
+        .parse()
+        .map_err(|_| anyhow::anyhow!("Invalid size: {}", size))?;
+    let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" | "kib" => 1 << 10,
+        "m" | "mb" | "mib" => 1 << 20,
+        "g" | "gb" | "gib" => 1 << 30,
+        _ => anyhow::bail!("Invalid size unit, expect K, M or G: {}", size),
+    };
+    match number.checked_mul(multiplier) {
+        Some(0) | None => anyhow::bail!("Invalid size: {}", size),
+        Some(n) => Ok(n),
+    }
+}
+
+fn parse_diff_format(format: &str) -> Result<DiffFormat, anyhow::Error> {
+    format.parse()
+}
+
+fn parse_rename(rename: &str) -> Result<Rename, anyhow::Error> {
+    rename.parse()
+}
+
+fn parse_on_error(policy: &str) -> Result<OnError, anyhow::Error> {
+    policy.parse()
+}
+
+fn parse_sort_key(key: &str) -> Result<SortKey, anyhow::Error> {
+    key.parse()
+}
+
+fn parse_encoding(encoding: &str) -> Result<CsvEncoding, anyhow::Error> {
+    encoding.parse()
+}
+
+fn parse_delimiter(delimiter: &str) -> Result<CsvDelimiter, anyhow::Error> {
+    delimiter.parse()
+}
+
 impl From<OutputFormat> for &'static str {
     fn from(format: OutputFormat) -> Self {
         match format {
             OutputFormat::Json => "json",
             OutputFormat::Yaml => "yaml",
+            OutputFormat::Ndjson => "ndjson",
+            OutputFormat::Csv => "csv",
+            OutputFormat::Toml => "toml",
+            OutputFormat::Xml => "xml",
+            OutputFormat::Markdown => "md",
+            OutputFormat::Html => "html",
+            OutputFormat::Xlsx => "xlsx",
+            OutputFormat::Parquet => "parquet",
+            OutputFormat::Arrow => "arrow",
         }
     }
 }
@@ -60,14 +705,281 @@ impl FromStr for OutputFormat {
     fn from_str(format: &str) -> Result<OutputFormat, anyhow::Error> {
         match format {
             "json" => Ok(OutputFormat::Json),
-            "yaml" => Ok(OutputFormat::Yaml),
+            "yaml" | "yml" => Ok(OutputFormat::Yaml),
+            "ndjson" | "jsonl" => Ok(OutputFormat::Ndjson),
+            "csv" => Ok(OutputFormat::Csv),
+            "toml" => Ok(OutputFormat::Toml),
+            "xml" => Ok(OutputFormat::Xml),
+            "md" | "markdown" => Ok(OutputFormat::Markdown),
+            "html" | "htm" => Ok(OutputFormat::Html),
+            "xlsx" => Ok(OutputFormat::Xlsx),
+            "parquet" => Ok(OutputFormat::Parquet),
+            "arrow" | "ipc" | "feather" => Ok(OutputFormat::Arrow),
             v => Err(anyhow::anyhow!("Unsupported format: {}", v)),
         }
     }
 }
 
+impl OutputFormat {
+    /// guess the format from the file extension, anything unknown is treated as csv.
+    /// a compression extension is skipped, so players.json.gz is json
+    pub fn from_path(path: &str) -> Self {
+        std::path::Path::new(crate::strip_compression(path))
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(|ext| ext.to_ascii_lowercase().parse().ok())
+            .unwrap_or(OutputFormat::Csv)
+    }
+
+    /// xlsx, parquet and arrow can't be streamed to stdout
+    pub fn is_binary(&self) -> bool {
+        matches!(
+            self,
+            OutputFormat::Xlsx | OutputFormat::Parquet | OutputFormat::Arrow
+        )
+    }
+}
+
 impl fmt::Display for OutputFormat {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}", Into::<&str>::into(*self))
     }
 }
+
+impl FromStr for CsvDelimiter {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(CsvDelimiter::Auto),
+            "tab" | "\\t" => Ok(CsvDelimiter::Char(b'\t')),
+            v if v.len() == 1 => Ok(CsvDelimiter::Char(v.as_bytes()[0])),
+            v => Err(anyhow::anyhow!("Invalid delimiter: {}", v)),
+        }
+    }
+}
+
+impl fmt::Display for CsvDelimiter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CsvDelimiter::Auto => write!(f, "auto"),
+            CsvDelimiter::Char(b'\t') => write!(f, "tab"),
+            CsvDelimiter::Char(c) => write!(f, "{}", *c as char),
+        }
+    }
+}
+
+impl FromStr for CsvEncoding {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(CsvEncoding::Auto),
+            // 按WHATWG的标准，latin1即windows-1252
+            v => Encoding::for_label(v.as_bytes())
+                .map(CsvEncoding::Label)
+                .ok_or_else(|| anyhow::anyhow!("Unsupported encoding: {}", v)),
+        }
+    }
+}
+
+impl fmt::Display for CsvEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CsvEncoding::Auto => write!(f, "auto"),
+            CsvEncoding::Label(e) => write!(f, "{}", e.name().to_ascii_lowercase()),
+        }
+    }
+}
+
+impl From<ColumnType> for &'static str {
+    fn from(ty: ColumnType) -> Self {
+        match ty {
+            ColumnType::String => "string",
+            ColumnType::Int => "int",
+            ColumnType::Float => "float",
+            ColumnType::Bool => "bool",
+            ColumnType::Date => "date",
+        }
+    }
+}
+
+impl FromStr for ColumnType {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "string" | "str" => Ok(ColumnType::String),
+            "int" | "integer" => Ok(ColumnType::Int),
+            "float" | "number" => Ok(ColumnType::Float),
+            "bool" | "boolean" => Ok(ColumnType::Bool),
+            "date" => Ok(ColumnType::Date),
+            v => Err(anyhow::anyhow!("Unsupported column type: {}", v)),
+        }
+    }
+}
+
+impl fmt::Display for ColumnType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
+
+impl FromStr for ColumnTypeOverride {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // 列名里可能有空格，如"Kit Number"，所以按最后一个=拆分
+        let (column, ty) = s
+            .rsplit_once('=')
+            .ok_or_else(|| anyhow::anyhow!("Invalid type override, expect column=type: {}", s))?;
+        Ok(ColumnTypeOverride {
+            column: column.trim().to_string(),
+            ty: ty.trim().parse()?,
+        })
+    }
+}
+
+impl From<JoinKind> for &'static str {
+    fn from(how: JoinKind) -> Self {
+        match how {
+            JoinKind::Inner => "inner",
+            JoinKind::Left => "left",
+            JoinKind::Outer => "outer",
+        }
+    }
+}
+
+impl FromStr for JoinKind {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "inner" => Ok(JoinKind::Inner),
+            "left" => Ok(JoinKind::Left),
+            "outer" | "full" => Ok(JoinKind::Outer),
+            v => Err(anyhow::anyhow!("Unsupported join: {}", v)),
+        }
+    }
+}
+
+impl fmt::Display for JoinKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
+
+impl From<Keep> for &'static str {
+    fn from(keep: Keep) -> Self {
+        match keep {
+            Keep::First => "first",
+            Keep::Last => "last",
+        }
+    }
+}
+
+impl FromStr for Keep {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "first" => Ok(Keep::First),
+            "last" => Ok(Keep::Last),
+            v => Err(anyhow::anyhow!("Invalid keep, expect first or last: {}", v)),
+        }
+    }
+}
+
+impl fmt::Display for Keep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
+
+impl From<DiffFormat> for &'static str {
+    fn from(format: DiffFormat) -> Self {
+        match format {
+            DiffFormat::Text => "text",
+            DiffFormat::Json => "json",
+            DiffFormat::Csv => "csv",
+        }
+    }
+}
+
+impl FromStr for DiffFormat {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "text" => Ok(DiffFormat::Text),
+            "json" => Ok(DiffFormat::Json),
+            "csv" => Ok(DiffFormat::Csv),
+            v => Err(anyhow::anyhow!(
+                "Invalid diff format, expect text, json or csv: {}",
+                v
+            )),
+        }
+    }
+}
+
+impl fmt::Display for DiffFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
+
+impl From<OnError> for &'static str {
+    fn from(policy: OnError) -> Self {
+        match policy {
+            OnError::Fail => "fail",
+            OnError::Skip => "skip",
+            OnError::Report => "report",
+        }
+    }
+}
+
+impl FromStr for OnError {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fail" => Ok(OnError::Fail),
+            "skip" => Ok(OnError::Skip),
+            "report" => Ok(OnError::Report),
+            v => Err(anyhow::anyhow!(
+                "Invalid error policy, expect fail, skip or report: {}",
+                v
+            )),
+        }
+    }
+}
+
+impl fmt::Display for OnError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
+
+impl FromStr for SortKey {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (column, desc) = match s.rsplit_once(':') {
+            Some((column, "desc")) => (column, true),
+            Some((column, "asc")) => (column, false),
+            _ => (s, false),
+        };
+        let column = column.trim();
+        if column.is_empty() {
+            anyhow::bail!("Invalid sort key: {}", s);
+        }
+        Ok(SortKey {
+            column: column.to_string(),
+            desc,
+        })
+    }
+}
+
+impl FromStr for Rename {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (from, to) = s
+            .split_once('=')
+            .ok_or_else(|| anyhow::anyhow!("Invalid rename, expect old=new: {}", s))?;
+        Ok(Rename {
+            from: from.trim().to_string(),
+            to: to.trim().to_string(),
+        })
+    }
+}
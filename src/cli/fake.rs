@@ -0,0 +1,28 @@
+use clap::Parser;
+
+use crate::CmdExecutor;
+
+use super::{verify_file, CsvOutputOpts};
+
+#[derive(Debug, Parser)]
+pub struct FakeOpts {
+    /// yaml schema of the rows, the bundled player schema when not given
+    #[arg(long, value_parser = verify_file)]
+    pub schema: Option<String>,
+
+    #[arg(short = 'n', long, default_value_t = 100)]
+    pub rows: usize,
+
+    /// seed of the random generator, the same seed generates the same rows
+    #[arg(long)]
+    pub seed: Option<u64>,
+
+    #[command(flatten)]
+    pub target: CsvOutputOpts,
+}
+
+impl CmdExecutor for FakeOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        crate::process_fake(self.schema.as_deref(), self.rows, self.seed, &self.target)
+    }
+}
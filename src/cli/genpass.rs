@@ -1,34 +1,408 @@
-use clap::Parser;
-use zxcvbn::zxcvbn;
+use std::{
+    fmt,
+    io::{self, IsTerminal},
+    str::FromStr,
+};
 
-use crate::{process_genpass, CmdExecutor};
+use crate::{
+    derive_password, generate_password, get_content, load_labels, load_wordlist,
+    process_genpass_check, process_passphrase, write_passwords, CmdExecutor, GeneratedPassword,
+    PasswordPolicy, PasswordSpec,
+};
+use clap::{ArgAction, Parser};
+use enum_dispatch::enum_dispatch;
+
+use super::verify_file;
+
+#[derive(Debug, Parser)]
+#[command(args_conflicts_with_subcommands = true)]
+pub struct GenPassCommand {
+    #[command(subcommand)]
+    pub cmd: Option<GenPassSubCommand>,
+
+    // 没有子命令时生成密码，和rcli csv一样
+    #[command(flatten)]
+    pub generate: GenPassOpts,
+}
+
+#[derive(Debug, Parser)]
+#[enum_dispatch(CmdExecutor)]
+pub enum GenPassSubCommand {
+    #[command(about = "Check passwords read line by line against a policy with zxcvbn")]
+    Check(GenPassCheckOpts),
+    #[command(about = "Derive the password of a site from a master passphrase, nothing is stored")]
+    Derive(GenPassDeriveOpts),
+}
+
+#[derive(Debug, Parser)]
+pub struct GenPassCheckOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+
+    #[arg(long, value_parser = verify_file)]
+    pub policy: Option<String>,
+}
+
+#[derive(Debug, Parser)]
+pub struct GenPassDeriveOpts {
+    #[arg(long)]
+    pub site: String,
+
+    #[arg(long, default_value = "")]
+    pub login: String,
+
+    /// bump it to change the password of a site
+    #[arg(long, default_value_t = 1)]
+    pub counter: u32,
+
+    /// read the master passphrase from a file ("-" for stdin) instead of prompting for it
+    #[arg(long, value_parser = verify_file)]
+    pub master_file: Option<String>,
+
+    #[command(flatten)]
+    pub chars: PasswordCharOpts,
+}
 
 #[derive(Debug, Parser)]
 pub struct GenPassOpts {
+    #[command(flatten)]
+    pub chars: PasswordCharOpts,
+
+    /// generate a passphrase of this many words instead of a password
+    #[arg(short, long)]
+    pub words: Option<usize>,
+
+    /// one word per line, diceware lines like "11111 abacus" work too.
+    /// the bundled EFF large wordlist when not given
+    #[arg(long, value_parser = verify_file, requires = "words")]
+    pub wordlist: Option<String>,
+
+    /// separator between the words of a passphrase
+    #[arg(long, default_value = "-")]
+    pub separator: String,
+
+    /// lower, upper, title, or random (title or lower per word)
+    #[arg(long, value_parser = parse_capitalize, default_value = "lower")]
+    pub capitalize: Capitalize,
+
+    /// toml policy the password must meet, it is regenerated until it does
+    #[arg(long, value_parser = verify_file)]
+    pub policy: Option<String>,
+
+    /// number of passwords, the number of labels by default
+    #[arg(short, long)]
+    pub count: Option<usize>,
+
+    /// one label per line, e.g. a device name, each password gets the label of its line
+    #[arg(long, value_parser = verify_file)]
+    pub labels: Option<String>,
+
+    /// plain, json or csv. json and csv include the score and entropy of each password
+    #[arg(long, value_parser = parse_pass_format, default_value = "plain")]
+    pub format: PassFormat,
+
+    /// digits added to the end of random words of a passphrase
+    #[arg(long, default_value_t = 0)]
+    pub insert_digits: usize,
+
+    /// symbols added to the end of random words of a passphrase
+    #[arg(long, default_value_t = 0)]
+    pub insert_symbols: usize,
+}
+
+/// the character classes of a password, shared by generated and derived passwords
+#[derive(Debug, Parser)]
+pub struct PasswordCharOpts {
     #[arg(short, long, default_value_t = 16)]
     pub length: u8,
-    #[arg(long, default_value_t = true)]
+    // ArgAction::Set才能用--symbol false关掉，默认的SetTrue永远是true
+    #[arg(long, action = ArgAction::Set, num_args = 0..=1, default_value_t = true, default_missing_value = "true")]
     pub uppercase: bool,
-    #[arg(long, default_value_t = true)]
+    #[arg(long, action = ArgAction::Set, num_args = 0..=1, default_value_t = true, default_missing_value = "true")]
     pub lowercase: bool,
-    #[arg(long, default_value_t = true)]
+    #[arg(long, action = ArgAction::Set, num_args = 0..=1, default_value_t = true, default_missing_value = "true")]
     pub number: bool,
-    #[arg(long, default_value_t = true)]
+    #[arg(long, action = ArgAction::Set, num_args = 0..=1, default_value_t = true, default_missing_value = "true")]
     pub symbol: bool,
+
+    /// symbols to use instead of the default "!@#$%^&*_"
+    #[arg(long)]
+    pub symbol_set: Option<String>,
+
+    /// draw from these characters instead of the classes above
+    #[arg(long)]
+    pub charset: Option<String>,
+
+    /// characters never to use, e.g. "O0l1"
+    #[arg(long, default_value = "")]
+    pub exclude: String,
+
+    /// minimum number of uppercase letters, 1 by default
+    #[arg(long)]
+    pub min_upper: Option<usize>,
+    #[arg(long)]
+    pub min_lower: Option<usize>,
+    #[arg(long)]
+    pub min_digits: Option<usize>,
+    #[arg(long)]
+    pub min_symbols: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PassFormat {
+    Plain,
+    Json,
+    Csv,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Capitalize {
+    Lower,
+    Upper,
+    Title,
+    Random,
+}
+
+impl CmdExecutor for GenPassCommand {
+    async fn execute(self) -> anyhow::Result<()> {
+        match self.cmd {
+            Some(cmd) => cmd.execute().await,
+            None => self.generate.execute().await,
+        }
+    }
+}
+
+impl CmdExecutor for GenPassCheckOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let policy = match &self.policy {
+            Some(path) => PasswordPolicy::load(path)?,
+            None => PasswordPolicy::default(),
+        };
+        let reports = process_genpass_check(&self.input, &policy)?;
+        // 不回显密码本身，只用序号区分
+        for (i, report) in reports.iter().enumerate() {
+            let status = if report.violations.is_empty() {
+                "ok"
+            } else {
+                "FAIL"
+            };
+            println!("password {}: {}", i + 1, status);
+            println!(
+                "  score: {}/4 (about 10^{:.1} guesses)",
+                report.score, report.guesses_log10
+            );
+            if let Some(warning) = &report.warning {
+                println!("  warning: {}", warning);
+            }
+            for suggestion in &report.suggestions {
+                println!("  suggestion: {}", suggestion);
+            }
+            for (scenario, time) in &report.crack_times {
+                println!("  crack time, {}: {}", scenario, time);
+            }
+            for violation in &report.violations {
+                println!("  policy: {}", violation);
+            }
+        }
+        let failed = reports.iter().filter(|r| !r.violations.is_empty()).count();
+        if failed > 0 {
+            anyhow::bail!(
+                "{} of {} passwords don't meet the policy",
+                failed,
+                reports.len()
+            );
+        }
+        Ok(())
+    }
 }
 
 impl CmdExecutor for GenPassOpts {
     async fn execute(self) -> anyhow::Result<()> {
-        let ret = process_genpass(
-            self.length,
-            self.uppercase,
-            self.lowercase,
-            self.number,
-            self.symbol,
-        )?;
-        println!("{}", &ret);
-        let estimate = zxcvbn(&ret, &[]);
-        eprintln!("Password strength: {}", estimate.score());
+        let policy = match &self.policy {
+            Some(path) => PasswordPolicy::load(path)?,
+            None => PasswordPolicy::default(),
+        };
+        let labels = match &self.labels {
+            Some(path) => load_labels(path)?,
+            None => vec![],
+        };
+        let count = match (self.count, labels.len()) {
+            (None, 0) => 1,
+            (None, n) => n,
+            (Some(count), n) if n > 0 && count != n => anyhow::bail!(
+                "--count {} doesn't match the {} labels of {}",
+                count,
+                n,
+                self.labels.as_deref().unwrap_or_default()
+            ),
+            (Some(count), _) => count,
+        };
+        let mut labels = labels.into_iter();
+
+        let mut passwords = Vec::with_capacity(count);
+        if let Some(words) = self.words {
+            let wordlist = load_wordlist(self.wordlist.as_deref())?;
+            let mut spec = self.chars.spec();
+            let (mut digits, mut symbols) = (self.insert_digits, self.insert_symbols);
+            policy.apply_passphrase(
+                &mut spec,
+                self.capitalize,
+                &self.separator,
+                &mut digits,
+                &mut symbols,
+            )?;
+            for _ in 0..count {
+                let mut entropy = 0.0;
+                let phrase = policy.generate(|| {
+                    let passphrase = process_passphrase(
+                        &wordlist,
+                        words,
+                        &self.separator,
+                        self.capitalize,
+                        &spec,
+                        digits,
+                        symbols,
+                    )?;
+                    entropy = passphrase.entropy;
+                    Ok(passphrase.phrase)
+                })?;
+                passwords.push(GeneratedPassword::new(labels.next(), phrase, entropy));
+            }
+        } else {
+            let mut spec = self.chars.spec();
+            policy.apply(&mut spec);
+            let rules = spec.rules()?;
+            let entropy = rules.entropy();
+            let mut rng = rand::thread_rng();
+            for _ in 0..count {
+                let password = policy.generate(|| Ok(generate_password(&rules, &mut rng)))?;
+                passwords.push(GeneratedPassword::new(labels.next(), password, entropy));
+            }
+        }
+
+        write_passwords(&passwords, self.format, io::stdout().lock())?;
+        // 单个密码时照旧把强度打到stderr，批量的去看json/csv里的字段
+        if let [p] = passwords.as_slice() {
+            if self.format == PassFormat::Plain {
+                if self.words.is_some() {
+                    eprintln!("Entropy: {:.1} bits", p.entropy);
+                } else {
+                    eprintln!("Password strength: {}", p.score);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl CmdExecutor for GenPassDeriveOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let rules = self.chars.spec().rules()?;
+        let master = match &self.master_file {
+            Some(path) => {
+                let content = String::from_utf8(get_content(path)?)?;
+                // 只去掉文件末尾的换行，口令里的空格要保留
+                let master = content.strip_suffix('\n').unwrap_or(&content);
+                master.strip_suffix('\r').unwrap_or(master).to_string()
+            }
+            None if io::stdin().is_terminal() => rpassword::prompt_password("Master passphrase: ")?,
+            None => {
+                anyhow::bail!("No terminal to prompt for the master passphrase, use --master-file")
+            }
+        };
+        let password = derive_password(&master, &self.site, &self.login, self.counter, &rules)?;
+        println!("{}", password);
         Ok(())
     }
 }
+
+impl PasswordCharOpts {
+    pub fn spec(&self) -> PasswordSpec {
+        PasswordSpec {
+            length: self.length as usize,
+            upper: self.uppercase,
+            lower: self.lowercase,
+            number: self.number,
+            symbol: self.symbol,
+            symbol_set: self.symbol_set.clone(),
+            charset: self.charset.clone(),
+            exclude: self.exclude.clone(),
+            min_upper: self.min_upper,
+            min_lower: self.min_lower,
+            min_digits: self.min_digits,
+            min_symbols: self.min_symbols,
+        }
+    }
+}
+
+fn parse_pass_format(s: &str) -> Result<PassFormat, anyhow::Error> {
+    s.parse()
+}
+
+fn parse_capitalize(s: &str) -> Result<Capitalize, anyhow::Error> {
+    s.parse()
+}
+
+impl From<PassFormat> for &'static str {
+    fn from(format: PassFormat) -> Self {
+        match format {
+            PassFormat::Plain => "plain",
+            PassFormat::Json => "json",
+            PassFormat::Csv => "csv",
+        }
+    }
+}
+
+impl FromStr for PassFormat {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "plain" | "text" => Ok(PassFormat::Plain),
+            "json" => Ok(PassFormat::Json),
+            "csv" => Ok(PassFormat::Csv),
+            v => Err(anyhow::anyhow!(
+                "Invalid format, expect plain, json or csv: {}",
+                v
+            )),
+        }
+    }
+}
+
+impl fmt::Display for PassFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
+
+impl From<Capitalize> for &'static str {
+    fn from(capitalize: Capitalize) -> Self {
+        match capitalize {
+            Capitalize::Lower => "lower",
+            Capitalize::Upper => "upper",
+            Capitalize::Title => "title",
+            Capitalize::Random => "random",
+        }
+    }
+}
+
+impl FromStr for Capitalize {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "lower" | "none" => Ok(Capitalize::Lower),
+            "upper" => Ok(Capitalize::Upper),
+            "title" => Ok(Capitalize::Title),
+            "random" => Ok(Capitalize::Random),
+            v => Err(anyhow::anyhow!(
+                "Invalid capitalization, expect lower, upper, title or random: {}",
+                v
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Capitalize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
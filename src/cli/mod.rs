@@ -1,5 +1,6 @@
 mod base64;
 mod csv;
+mod fake;
 mod genpass;
 mod http;
 mod jwt;
@@ -7,7 +8,7 @@ mod text;
 
 use std::path::{Path, PathBuf};
 
-pub use self::{base64::*, csv::*, genpass::*, http::*, jwt::*, text::*};
+pub use self::{base64::*, csv::*, fake::*, genpass::*, http::*, jwt::*, text::*};
 use clap::Parser;
 use enum_dispatch::enum_dispatch;
 
@@ -21,12 +22,16 @@ pub struct Opts {
 
 #[derive(Debug, Parser)]
 #[enum_dispatch(CmdExecutor)]
+// 命令行参数只解析一次，不在意enum的大小
+#[allow(clippy::large_enum_variant)]
 pub enum SubCommand {
     //子命令，-- csv，中间有个空格
     #[command(name = "csv", about = "Show CSV, or convert CSV to other formats")]
-    Csv(CsvOpts),
+    Csv(CsvCommand),
+    #[command(name = "fake", about = "Generate fake rows from a schema")]
+    Fake(FakeOpts),
     #[command(name = "genpass", about = "Generate a random password")]
-    GenPass(GenPassOpts),
+    GenPass(GenPassCommand),
     #[command(subcommand)]
     Base64(Base64SubCommand),
     #[command(subcommand)]
@@ -1,3 +1,5 @@
+use std::io;
+
 use clap::Parser;
 
 use rcli::{CmdExecutor, Opts};
@@ -6,8 +8,25 @@ use rcli::{CmdExecutor, Opts};
 async fn main() -> anyhow::Result<()> {
     tracing_subscriber::fmt::init();
     let opts = Opts::parse();
-    opts.cmd.execute().await?;
-    Ok(())
+    match opts.cmd.execute().await {
+        // 下游（比如head）读够了就关掉管道，这不算出错
+        Err(e) if is_broken_pipe(&e) => Ok(()),
+        ret => ret,
+    }
+}
+
+fn is_broken_pipe(e: &anyhow::Error) -> bool {
+    let broken_pipe = |e: &io::Error| e.kind() == io::ErrorKind::BrokenPipe;
+    e.chain().any(|e| {
+        if let Some(e) = e.downcast_ref::<io::Error>() {
+            return broken_pipe(e);
+        }
+        // csv::Error不把io错误当source暴露出来
+        match e.downcast_ref::<csv::Error>().map(|e| e.kind()) {
+            Some(csv::ErrorKind::Io(e)) => broken_pipe(e),
+            _ => false,
+        }
+    })
 }
 
 #[cfg(test)]
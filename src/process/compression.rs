@@ -0,0 +1,177 @@
+use std::{
+    cell::RefCell,
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, StdoutLock, Write},
+    path::Path,
+    rc::Rc,
+};
+
+use anyhow::Result;
+use bzip2::{read::MultiBzDecoder, write::BzEncoder};
+use flate2::{read::MultiGzDecoder, write::GzEncoder};
+
+use crate::get_reader;
+
+/// compression of a file, known from its extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+    Bzip2,
+}
+
+impl Compression {
+    pub fn from_path(path: &str) -> Option<Self> {
+        let ext = Path::new(path).extension()?.to_str()?.to_ascii_lowercase();
+        match ext.as_str() {
+            "gz" | "gzip" => Some(Compression::Gzip),
+            "zst" | "zstd" => Some(Compression::Zstd),
+            "bz2" => Some(Compression::Bzip2),
+            _ => None,
+        }
+    }
+}
+
+/// the path without its compression extension, e.g. players.csv for players.csv.gz
+pub fn strip_compression(path: &str) -> &str {
+    match Compression::from_path(path) {
+        Some(_) => path.rsplit_once('.').map(|(stem, _)| stem).unwrap_or(path),
+        None => path,
+    }
+}
+
+/// open a file, or stdin for "-". .gz, .zst and .bz2 files are decompressed on the fly
+pub fn open_input(input: &str) -> Result<Box<dyn Read>> {
+    let reader = get_reader(input)?;
+    if input == "-" {
+        return Ok(reader);
+    }
+    Ok(match Compression::from_path(input) {
+        // 多个gzip/bzip2成员拼接的文件（比如cat a.gz b.gz）也要读完
+        Some(Compression::Gzip) => Box::new(MultiGzDecoder::new(BufReader::new(reader))),
+        Some(Compression::Zstd) => Box::new(zstd::Decoder::new(reader)?),
+        Some(Compression::Bzip2) => Box::new(MultiBzDecoder::new(BufReader::new(reader))),
+        None => reader,
+    })
+}
+
+/// an output file or stdout. compressed streams are only complete after `finish`, which
+/// reports the errors dropping them would swallow. clones share the same output
+#[derive(Clone)]
+pub struct OutputFile(Rc<RefCell<Option<Output>>>);
+
+enum Output {
+    Stdout(StdoutLock<'static>),
+    Plain(BufWriter<File>),
+    Gzip(BufWriter<GzEncoder<File>>),
+    Zstd(BufWriter<zstd::Encoder<'static, File>>),
+    Bzip2(BufWriter<BzEncoder<File>>),
+}
+
+/// create a file, or write to stdout for "-". .gz, .zst and .bz2 files are compressed
+pub fn create_output(output: &str) -> Result<OutputFile> {
+    if output == "-" {
+        return Ok(OutputFile::stdout());
+    }
+    let file = File::create(output)?;
+    let output = match Compression::from_path(output) {
+        Some(Compression::Gzip) => Output::Gzip(BufWriter::new(GzEncoder::new(
+            file,
+            flate2::Compression::default(),
+        ))),
+        Some(Compression::Zstd) => Output::Zstd(BufWriter::new(zstd::Encoder::new(file, 0)?)),
+        Some(Compression::Bzip2) => Output::Bzip2(BufWriter::new(BzEncoder::new(
+            file,
+            bzip2::Compression::default(),
+        ))),
+        None => Output::Plain(BufWriter::new(file)),
+    };
+    Ok(OutputFile(Rc::new(RefCell::new(Some(output)))))
+}
+
+impl OutputFile {
+    pub fn stdout() -> Self {
+        Self(Rc::new(RefCell::new(Some(Output::Stdout(
+            io::stdout().lock(),
+        )))))
+    }
+
+    /// flush and write the end of the compressed stream, later writes fail
+    pub fn finish(&self) -> io::Result<()> {
+        let Some(output) = self.0.borrow_mut().take() else {
+            return Ok(());
+        };
+        match output {
+            Output::Stdout(mut w) => w.flush(),
+            Output::Plain(w) => finished(w).map(drop),
+            Output::Gzip(w) => finished(w)?.finish().map(drop),
+            Output::Zstd(w) => finished(w)?.finish().map(drop),
+            Output::Bzip2(w) => finished(w)?.finish().map(drop),
+        }
+    }
+}
+
+fn finished<W: Write>(w: BufWriter<W>) -> io::Result<W> {
+    w.into_inner().map_err(|e| e.into_error())
+}
+
+impl Write for OutputFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.0.borrow_mut().as_mut() {
+            Some(Output::Stdout(w)) => w.write(buf),
+            Some(Output::Plain(w)) => w.write(buf),
+            Some(Output::Gzip(w)) => w.write(buf),
+            Some(Output::Zstd(w)) => w.write(buf),
+            Some(Output::Bzip2(w)) => w.write(buf),
+            None => Err(io::Error::other("the output is already finished")),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.0.borrow_mut().as_mut() {
+            Some(Output::Stdout(w)) => w.flush(),
+            Some(Output::Plain(w)) => w.flush(),
+            Some(Output::Gzip(w)) => w.flush(),
+            Some(Output::Zstd(w)) => w.flush(),
+            Some(Output::Bzip2(w)) => w.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compression_round_trip() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let content = "Name,Kit Number\nPaulo Dybala,10\n".repeat(100);
+        for ext in ["csv", "csv.gz", "csv.zst", "csv.bz2"] {
+            let path = dir.path().join(format!("players.{}", ext));
+            let path = path.to_str().unwrap();
+            let mut writer = create_output(path)?;
+            writer.write_all(content.as_bytes())?;
+            writer.finish()?;
+
+            let mut read = String::new();
+            open_input(path)?.read_to_string(&mut read)?;
+            assert_eq!(read, content);
+            assert!(strip_compression(path).ends_with("players.csv"));
+        }
+        Ok(())
+    }
+
+    // 写满的磁盘上，压缩流的结尾写不进去要报错，而不是在drop里被吞掉
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_finish_reports_errors() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("players.csv.gz");
+        std::os::unix::fs::symlink("/dev/full", &path)?;
+        let mut writer = create_output(path.to_str().unwrap())?;
+        writer.write_all(b"Name,Kit Number\n")?;
+        assert!(writer.finish().is_err());
+        Ok(())
+    }
+}
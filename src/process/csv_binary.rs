@@ -0,0 +1,328 @@
+use std::{fs::File, sync::Arc};
+
+use anyhow::Result;
+use arrow_array::{ArrayRef, BooleanArray, Float64Array, Int64Array, RecordBatch, StringArray};
+use arrow_ipc::writer::FileWriter;
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use chrono::Datelike;
+use parquet::arrow::ArrowWriter;
+use rust_xlsxwriter::{ExcelDateTime, Format, Workbook};
+use serde_json::Value;
+
+use crate::cli::OutputFormat;
+
+use super::{csv_convert::value_to_cell, csv_types::parse_date_with, csv_writer::RecordWriter};
+
+// parquet/arrow按批写入，schema由第一批推断
+const BATCH_ROWS: usize = 8192;
+
+/// xlsx output: a bold header row, then numbers, booleans and ISO dates as typed cells
+pub struct XlsxWriter {
+    workbook: Workbook,
+    file: Option<File>,
+    columns: Vec<String>,
+    row: u32,
+    date_format: Format,
+}
+
+/// parquet or arrow IPC output, with the schema inferred from the first batch of records
+pub struct ArrowRecordWriter {
+    format: OutputFormat,
+    file: Option<File>,
+    columns: Vec<String>,
+    pending: Vec<Value>,
+    schema: Option<SchemaRef>,
+    sink: Option<ArrowSink>,
+}
+
+enum ArrowSink {
+    Parquet(ArrowWriter<File>),
+    Ipc(FileWriter<File>),
+}
+
+/// writer for the binary formats, which always go to a file
+pub fn binary_record_writer(
+    format: OutputFormat,
+    columns: &[String],
+    file: File,
+) -> Result<Box<dyn RecordWriter>> {
+    let columns = columns.to_vec();
+    match format {
+        OutputFormat::Xlsx => Ok(Box::new(XlsxWriter::new(columns, file)?)),
+        OutputFormat::Parquet | OutputFormat::Arrow => Ok(Box::new(ArrowRecordWriter {
+            format,
+            file: Some(file),
+            columns,
+            pending: Vec::with_capacity(BATCH_ROWS),
+            schema: None,
+            sink: None,
+        })),
+        f => anyhow::bail!("{} is not a binary format", f),
+    }
+}
+
+impl XlsxWriter {
+    fn new(columns: Vec<String>, file: File) -> Result<Self> {
+        let mut workbook = Workbook::new();
+        // constant memory模式按行写出，大文件也不会全部留在内存里
+        let sheet = workbook.add_worksheet_with_constant_memory();
+        let bold = Format::new().set_bold();
+        for (i, column) in columns.iter().enumerate() {
+            sheet.write_string_with_format(0, col_num(i)?, column, &bold)?;
+        }
+        sheet.set_freeze_panes(1, 0)?;
+        Ok(Self {
+            workbook,
+            file: Some(file),
+            columns,
+            row: 0,
+            date_format: Format::new().set_num_format("yyyy-mm-dd"),
+        })
+    }
+}
+
+impl RecordWriter for XlsxWriter {
+    fn write_record(&mut self, record: &Value) -> Result<()> {
+        self.row += 1;
+        let sheet = self.workbook.worksheet_from_index(0)?;
+        for (i, column) in self.columns.iter().enumerate() {
+            let col = col_num(i)?;
+            match record.get(column).unwrap_or(&Value::Null) {
+                Value::Null => {}
+                Value::Bool(b) => {
+                    sheet.write_boolean(self.row, col, *b)?;
+                }
+                Value::Number(n) => {
+                    sheet.write_number(self.row, col, n.as_f64().unwrap_or_default())?;
+                }
+                Value::String(s) => {
+                    match parse_date_with(s, "%Y-%m-%d").filter(|_| s.len() == 10) {
+                        Some(date) => {
+                            let date = ExcelDateTime::from_ymd(
+                                date.year() as u16,
+                                date.month() as u8,
+                                date.day() as u8,
+                            )?;
+                            sheet.write_datetime_with_format(
+                                self.row,
+                                col,
+                                &date,
+                                &self.date_format,
+                            )?;
+                        }
+                        None => {
+                            sheet.write_string(self.row, col, s)?;
+                        }
+                    }
+                }
+                v => {
+                    sheet.write_string(self.row, col, value_to_cell(v))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        if let Some(file) = self.file.take() {
+            self.workbook.save_to_writer(file)?;
+        }
+        Ok(())
+    }
+}
+
+fn col_num(i: usize) -> Result<u16> {
+    u16::try_from(i).map_err(|_| anyhow::anyhow!("Too many columns for xlsx: {}", i + 1))
+}
+
+impl RecordWriter for ArrowRecordWriter {
+    fn write_record(&mut self, record: &Value) -> Result<()> {
+        self.pending.push(record.clone());
+        if self.pending.len() == BATCH_ROWS {
+            self.flush_batch()?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        // 没有任何记录时也要写出一个只有schema的文件
+        if !self.pending.is_empty() || self.schema.is_none() {
+            self.flush_batch()?;
+        }
+        match self.sink.take() {
+            Some(ArrowSink::Parquet(writer)) => {
+                writer.close()?;
+            }
+            Some(ArrowSink::Ipc(mut writer)) => writer.finish()?,
+            None => {}
+        }
+        Ok(())
+    }
+}
+
+impl ArrowRecordWriter {
+    fn flush_batch(&mut self) -> Result<()> {
+        let schema = match &self.schema {
+            Some(schema) => schema.clone(),
+            None => {
+                let schema = infer_schema(&self.columns, &self.pending);
+                let file = self
+                    .file
+                    .take()
+                    .ok_or_else(|| anyhow::anyhow!("output is already closed"))?;
+                self.sink = Some(match self.format {
+                    OutputFormat::Parquet => {
+                        ArrowSink::Parquet(ArrowWriter::try_new(file, schema.clone(), None)?)
+                    }
+                    _ => ArrowSink::Ipc(FileWriter::try_new(file, &schema)?),
+                });
+                self.schema = Some(schema.clone());
+                schema
+            }
+        };
+        let batch = record_batch(&schema, &self.pending)?;
+        self.pending.clear();
+        match &mut self.sink {
+            Some(ArrowSink::Parquet(writer)) => writer.write(&batch)?,
+            Some(ArrowSink::Ipc(writer)) => writer.write(&batch)?,
+            None => {}
+        }
+        Ok(())
+    }
+}
+
+/// infer a nullable arrow type for each column; mixed types widen to float64 or utf8
+pub fn infer_schema(columns: &[String], records: &[Value]) -> SchemaRef {
+    let fields: Vec<Field> = columns
+        .iter()
+        .map(|column| {
+            let ty = records
+                .iter()
+                .filter_map(|r| r.get(column))
+                .fold(None, widen);
+            Field::new(column, ty.unwrap_or(DataType::Utf8), true)
+        })
+        .collect();
+    Arc::new(Schema::new(fields))
+}
+
+fn widen(ty: Option<DataType>, value: &Value) -> Option<DataType> {
+    let value_ty = match value {
+        Value::Null => return ty,
+        Value::Bool(_) => DataType::Boolean,
+        Value::Number(n) if n.is_i64() => DataType::Int64,
+        Value::Number(_) => DataType::Float64,
+        _ => DataType::Utf8,
+    };
+    Some(match (ty, value_ty) {
+        (None, t) => t,
+        (Some(a), b) if a == b => a,
+        (Some(DataType::Int64), DataType::Float64) | (Some(DataType::Float64), DataType::Int64) => {
+            DataType::Float64
+        }
+        _ => DataType::Utf8,
+    })
+}
+
+fn record_batch(schema: &SchemaRef, records: &[Value]) -> Result<RecordBatch> {
+    let mut arrays: Vec<ArrayRef> = Vec::with_capacity(schema.fields().len());
+    for field in schema.fields() {
+        let name = field.name();
+        let values = records.iter().map(|r| r.get(name).unwrap_or(&Value::Null));
+        let mismatch = |value: &Value| {
+            anyhow::anyhow!(
+                "column {}: {} does not match the type {} inferred from the first {} rows, force the type with --type",
+                name,
+                value,
+                field.data_type(),
+                BATCH_ROWS
+            )
+        };
+        let array: ArrayRef = match field.data_type() {
+            DataType::Boolean => Arc::new(
+                values
+                    .map(|v| match v {
+                        Value::Null => Ok(None),
+                        v => v.as_bool().map(Some).ok_or_else(|| mismatch(v)),
+                    })
+                    .collect::<Result<BooleanArray>>()?,
+            ),
+            DataType::Int64 => Arc::new(
+                values
+                    .map(|v| match v {
+                        Value::Null => Ok(None),
+                        v => v.as_i64().map(Some).ok_or_else(|| mismatch(v)),
+                    })
+                    .collect::<Result<Int64Array>>()?,
+            ),
+            DataType::Float64 => Arc::new(
+                values
+                    .map(|v| match v {
+                        Value::Null => Ok(None),
+                        v => v.as_f64().map(Some).ok_or_else(|| mismatch(v)),
+                    })
+                    .collect::<Result<Float64Array>>()?,
+            ),
+            _ => Arc::new(
+                values
+                    .map(|v| (!v.is_null()).then(|| value_to_cell(v)))
+                    .collect::<StringArray>(),
+            ),
+        };
+        arrays.push(array);
+    }
+    Ok(RecordBatch::try_new(schema.clone(), arrays)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_infer_schema_and_batch() -> Result<()> {
+        let columns: Vec<String> = ["name", "kit", "rating", "captain"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let records = vec![
+            json!({"name": "Buffon", "kit": 77, "rating": 88, "captain": null}),
+            json!({"name": "Chiellini", "kit": 3, "rating": 89.5, "captain": true}),
+        ];
+        let schema = infer_schema(&columns, &records);
+        let types: Vec<&DataType> = schema.fields().iter().map(|f| f.data_type()).collect();
+        assert_eq!(
+            types,
+            vec![
+                &DataType::Utf8,
+                &DataType::Int64,
+                &DataType::Float64,
+                &DataType::Boolean
+            ]
+        );
+        let batch = record_batch(&schema, &records)?;
+        assert_eq!(batch.num_rows(), 2);
+        assert_eq!(batch.column(3).null_count(), 1);
+
+        let bad = vec![json!({"name": "Dybala", "kit": "ten", "rating": 1, "captain": false})];
+        assert!(record_batch(&schema, &bad).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parquet_round_trip() -> Result<()> {
+        let columns = vec!["name".to_string(), "kit".to_string()];
+        let file = tempfile::tempfile()?;
+        let mut writer = binary_record_writer(OutputFormat::Parquet, &columns, file.try_clone()?)?;
+        writer.write_record(&json!({"name": "Dybala", "kit": 10}))?;
+        writer.write_record(&json!({"name": "Buffon", "kit": null}))?;
+        writer.finish()?;
+
+        let reader = parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder::try_new(file)?
+            .build()?;
+        let batches = reader.collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(batches[0].num_rows(), 2);
+        assert_eq!(batches[0].schema().field(1).data_type(), &DataType::Int64);
+        Ok(())
+    }
+}
@@ -1,13 +1,26 @@
 use anyhow::Result;
-use csv::Reader;
 use serde::{Deserialize, Serialize};
-use std::fs;
+use serde_json::Value;
+use std::{collections::HashSet, io::Read};
 
-use crate::cli::OutputFormat;
+use crate::cli::{CsvEncoding, CsvInputOpts, CsvOpts, OnError, OutputFormat};
+
+use super::{
+    compression::strip_compression,
+    csv_encoding::utf8_reader,
+    csv_errors::RejectHandler,
+    csv_mapping::ColumnMapper,
+    csv_nested::{flatten_record, nest_record, nested_columns},
+    csv_parallel::parallel_records,
+    csv_reader::open_flexible_csv_reader,
+    csv_types::ValueConverter,
+    csv_writer::{output_record_writer, RecordWriter},
+};
 
 #[derive(Debug, Deserialize, Serialize)]
 //命名规则，首字母大写，驼峰式，即name会自动对应Name
 #[serde(rename_all = "PascalCase")]
+#[allow(dead_code)]
 pub struct Player {
     name: String,
     position: String,
@@ -18,26 +31,250 @@ pub struct Player {
     kit: u8,
 }
 
-pub fn process_csv(input: &str, output: String, format: OutputFormat) -> Result<()> {
-    let mut reader = Reader::from_path(input)?;
-    let mut ret = Vec::with_capacity(128);
-    let headers = reader.headers()?.clone();
-    for result in reader.records() {
-        let record = result?;
-        // zip 将两个迭代器合并成一个元组的迭代器[(headers, record,...)]
-        // collect 将元组的迭代器转化为JSON VALUE，这里的JSON VALUE也实现了迭代器，所以能collect
-        let json_value = headers
-            .iter()
-            .zip(record.iter())
-            .collect::<serde_json::Value>();
-        ret.push(json_value);
-    }
-
-    let content = match format {
-        OutputFormat::Json => serde_json::to_string_pretty(&ret)?,
-        OutputFormat::Yaml => serde_yaml::to_string(&ret)?,
+/// --mapping, --select, --rename and --nested applied to one converted record of csv input,
+/// the same for the sequential and the parallel conversion
+pub struct RecordPipeline {
+    mapper: Option<ColumnMapper>,
+    nested: bool,
+}
+
+pub fn process_csv(opts: &CsvOpts, output: &str) -> Result<()> {
+    if opts.threads != 1 && opts.source.input_format() == OutputFormat::Csv {
+        let records = parallel_records(opts, opts.threads)?;
+        let columns = output_columns(opts, records.columns()).to_vec();
+        let (mut writer, mut rejects) = open_outputs(opts, &columns, output)?;
+        let converted = records.write_to(writer.as_mut(), &columns, &mut rejects)?;
+        writer.finish()?;
+        return rejects.finish(converted);
+    }
+    let (headers, records) = converted_records(opts)?;
+    let (mut writer, mut rejects) = open_outputs(opts, output_columns(opts, &headers), output)?;
+    let mut converted = 0;
+    for record in records {
+        match record {
+            Ok(record) => {
+                writer.write_record(&record)?;
+                converted += 1;
+            }
+            Err(e) => rejects.handle(e)?,
+        }
+    }
+    writer.finish()?;
+    rejects.finish(converted)
+}
+
+// 输出文件，以及--on-error report的旁路文件
+fn open_outputs(
+    opts: &CsvOpts,
+    columns: &[String],
+    output: &str,
+) -> Result<(Box<dyn RecordWriter>, RejectHandler)> {
+    let reject_file = match &opts.reject_file {
+        Some(path) => path.clone(),
+        // 输出到stdout时没有文件名可以借用，不能悄悄在当前目录写一个文件
+        None if output == "-" && opts.on_error == OnError::Report => {
+            anyhow::bail!("--on-error report needs --reject-file when the output is stdout")
+        }
+        None => format!("{}.rejected.csv", strip_compression(output)),
     };
+    let config = opts.target.writer_config(&opts.source.dialect);
+    let writer = output_record_writer(opts.target.format, columns, &config, Some(output))?;
+    let rejects = RejectHandler::new(opts.on_error, &reject_file)?;
+    Ok((writer, rejects))
+}
+
+/// the input records after --mapping, --select, --rename and --nested
+pub fn converted_records(opts: &CsvOpts) -> Result<(Vec<String>, RecordIter)> {
+    let (headers, records) = open_records(&opts.source)?;
+    // csv输入按点号路径组装成嵌套结构；其他格式的输入反过来展平成点号路径，方便转成csv
+    let csv_input = opts.source.input_format() == OutputFormat::Csv;
+    let (pipeline, columns) = RecordPipeline::new(opts, headers, opts.nested && csv_input)?;
+    let records: RecordIter = Box::new(records.map(move |r| pipeline.apply(r?)));
+    if opts.nested && !csv_input {
+        flattened_records(records)
+    } else {
+        Ok((columns, records))
+    }
+}
 
-    fs::write(output, content)?; // 这个返回的是()，结尾要返回Result
-    Ok(())
+impl RecordPipeline {
+    /// the pipeline for records with the given columns, together with the columns it outputs
+    pub fn new(opts: &CsvOpts, headers: Vec<String>, nested: bool) -> Result<(Self, Vec<String>)> {
+        let mapper = ColumnMapper::from_opts(
+            &headers,
+            opts.mapping.as_deref(),
+            &opts.select,
+            &opts.rename,
+        )?;
+        let mut columns = mapper.as_ref().map(|m| m.columns()).unwrap_or(headers);
+        if nested {
+            columns = nested_columns(&columns)?;
+        }
+        Ok((Self { mapper, nested }, columns))
+    }
+
+    pub fn apply(&self, record: Value) -> Result<Value> {
+        let record = match &self.mapper {
+            Some(mapper) => mapper.apply(&record),
+            None => record,
+        };
+        if self.nested {
+            nest_record(&record)
+        } else {
+            Ok(record)
+        }
+    }
+}
+
+pub type RecordIter = Box<dyn Iterator<Item = Result<Value>>>;
+
+/// open the input as a stream of converted records, together with the column names
+pub fn open_records(opts: &CsvInputOpts) -> Result<(Vec<String>, RecordIter)> {
+    open_records_with(opts, false)
+}
+
+/// like `open_records`, but --type overrides of columns the file doesn't have are skipped,
+/// for commands that read several files with one set of options
+pub fn open_records_skipping_unknown_types(
+    opts: &CsvInputOpts,
+) -> Result<(Vec<String>, RecordIter)> {
+    open_records_with(opts, true)
+}
+
+fn open_records_with(opts: &CsvInputOpts, skip_unknown: bool) -> Result<(Vec<String>, RecordIter)> {
+    match opts.input_format() {
+        OutputFormat::Csv => {
+            // csv输入按行读取、按行转换，内存占用和文件大小无关
+            let (reader, headers) = open_flexible_csv_reader(&opts.input, &opts.dialect)?;
+            let types: Vec<_> = opts
+                .types
+                .iter()
+                .filter(|o| !skip_unknown || headers.iter().any(|h| h == o.column))
+                .cloned()
+                .collect();
+            let converter = ValueConverter::new(&headers, opts.infer, &types)?;
+            let columns = headers.iter().map(String::from).collect();
+            // 格式不对的行返回RowError，由调用方按--on-error决定是否继续
+            let records = reader
+                .into_byte_records()
+                .map(move |result| converter.convert_raw(&headers, result?));
+            Ok((columns, Box::new(records)))
+        }
+        format => {
+            // 反向转换需要所有记录key的并集作为header，只能整体读入
+            let (headers, docs) = read_documents(&opts.input, opts.dialect.encoding, format)?;
+            Ok((headers, Box::new(docs.into_iter().map(Ok))))
+        }
+    }
+}
+
+// 展平之后的列是所有记录key的并集，只能整体读入
+fn flattened_records(records: RecordIter) -> Result<(Vec<String>, RecordIter)> {
+    let docs = records
+        .map(|r| r.map(|doc| flatten_record(&doc)))
+        .collect::<Result<Vec<_>>>()?;
+    let headers = collect_keys(&docs)?;
+    Ok((headers, Box::new(docs.into_iter().map(Ok))))
+}
+
+fn output_columns<'a>(opts: &'a CsvOpts, headers: &'a [String]) -> &'a [String] {
+    if opts.columns.is_empty() {
+        headers
+    } else {
+        &opts.columns
+    }
+}
+
+fn read_documents(
+    input: &str,
+    encoding: CsvEncoding,
+    format: OutputFormat,
+) -> Result<(Vec<String>, Vec<Value>)> {
+    let mut content = String::new();
+    utf8_reader(input, encoding)?.read_to_string(&mut content)?;
+    let docs = parse_documents(&content, format)?;
+    let headers = collect_keys(&docs)?;
+    Ok((headers, docs))
+}
+
+/// parse a JSON array, a YAML sequence or NDJSON lines into a list of objects
+fn parse_documents(content: &str, format: OutputFormat) -> Result<Vec<Value>> {
+    let value = match format {
+        OutputFormat::Json => serde_json::from_str(content)?,
+        OutputFormat::Yaml => serde_yaml::from_str(content)?,
+        OutputFormat::Ndjson => {
+            let mut docs = Vec::new();
+            for (i, line) in content.lines().enumerate() {
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let doc = serde_json::from_str(line)
+                    .map_err(|e| anyhow::anyhow!("line {}: {}", i + 1, e))?;
+                docs.push(doc);
+            }
+            Value::Array(docs)
+        }
+        OutputFormat::Csv => unreachable!("csv input is read by csv::Reader"),
+        f => anyhow::bail!("Unsupported input format: {}", f),
+    };
+    let docs = match value {
+        Value::Array(docs) => docs,
+        Value::Null => vec![],
+        v => vec![v],
+    };
+    Ok(docs)
+}
+
+// 所有记录key的并集，按第一次出现的顺序排列
+fn collect_keys(docs: &[Value]) -> Result<Vec<String>> {
+    let mut seen = HashSet::new();
+    let mut keys = Vec::new();
+    for (i, doc) in docs.iter().enumerate() {
+        let obj = doc
+            .as_object()
+            .ok_or_else(|| anyhow::anyhow!("record {} is not an object", i + 1))?;
+        for key in obj.keys() {
+            if seen.insert(key.as_str()) {
+                keys.push(key.clone());
+            }
+        }
+    }
+    Ok(keys)
+}
+
+/// strings are written as is, null becomes an empty cell, everything else as JSON
+pub fn value_to_cell(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        v => v.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{record_writer, WriterConfig};
+
+    #[test]
+    fn test_ndjson_to_csv_union_of_keys() -> Result<()> {
+        let content =
+            "{\"name\":\"Dybala\",\"kit\":10}\n\n{\"name\":\"Chiellini\",\"captain\":true}\n";
+        let docs = parse_documents(content, OutputFormat::Ndjson)?;
+        let keys = collect_keys(&docs)?;
+        assert_eq!(keys, vec!["name", "kit", "captain"]);
+        let mut buf = Vec::new();
+        let mut writer =
+            record_writer(OutputFormat::Csv, &keys, &WriterConfig::default(), &mut buf)?;
+        for doc in &docs {
+            writer.write_record(doc)?;
+        }
+        writer.finish()?;
+        drop(writer);
+        assert_eq!(
+            String::from_utf8(buf)?,
+            "name,kit,captain\nDybala,10,\nChiellini,,true\n"
+        );
+        Ok(())
+    }
 }
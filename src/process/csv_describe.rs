@@ -0,0 +1,346 @@
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    hash::{DefaultHasher, Hash, Hasher},
+    io::{self, IsTerminal},
+};
+
+use anyhow::Result;
+use serde_json::{json, Value};
+
+use crate::cli::CsvInputOpts;
+
+use super::{
+    csv_convert::{open_records, value_to_cell},
+    csv_query::compare_values,
+    csv_table::{Cell, TableRenderer},
+    csv_types::infer_value,
+};
+
+// HyperLogLog用2^12个寄存器，标准误差约1.6%
+const HLL_BITS: u32 = 12;
+
+/// statistics of one column, computed in a single pass
+#[derive(Debug)]
+pub struct ColumnStats {
+    pub name: String,
+    count: u64,
+    nulls: u64,
+    all_bool: bool,
+    all_int: bool,
+    all_number: bool,
+    min: Option<Value>,
+    max: Option<Value>,
+    // Welford算法，一遍得到均值和方差
+    mean: f64,
+    m2: f64,
+    numbers: u64,
+    // 不同值不超过distinct_limit时精确计数；超过后counts只保留高频值的近似计数
+    counts: HashMap<String, u64>,
+    hll: Option<HyperLogLog>,
+    distinct_limit: usize,
+}
+
+/// compute per-column statistics of the input, each column capped at `distinct_limit` exact distinct values
+pub fn describe_records(opts: &CsvInputOpts, distinct_limit: usize) -> Result<Vec<ColumnStats>> {
+    let (headers, records) = open_records(opts)?;
+    let mut columns: Vec<ColumnStats> = headers
+        .iter()
+        .map(|h| ColumnStats::new(h, distinct_limit))
+        .collect();
+    for record in records {
+        let record = record?;
+        for col in columns.iter_mut() {
+            col.update(record.get(&col.name).unwrap_or(&Value::Null));
+        }
+    }
+    Ok(columns)
+}
+
+pub fn process_csv_describe(
+    opts: &CsvInputOpts,
+    distinct_limit: usize,
+    top: usize,
+    json: bool,
+) -> Result<()> {
+    let columns = describe_records(opts, distinct_limit)?;
+    if json {
+        let stats: Vec<Value> = columns.iter().map(|c| c.to_json(top)).collect();
+        println!("{}", serde_json::to_string_pretty(&stats)?);
+        return Ok(());
+    }
+
+    let headers: Vec<String> = [
+        "column", "type", "count", "nulls", "distinct", "min", "max", "mean", "stddev", "top",
+    ]
+    .iter()
+    .map(|s| s.to_string())
+    .collect();
+    let rows: Vec<Vec<Cell>> = columns
+        .iter()
+        .map(|c| {
+            let distinct = if c.is_exact() {
+                c.distinct().to_string()
+            } else {
+                format!("~{}", c.distinct())
+            };
+            let top = c
+                .top(top)
+                .iter()
+                .map(|(v, n)| format!("{} ({})", v, n))
+                .collect::<Vec<_>>()
+                .join(", ");
+            vec![
+                Cell::text(&c.name),
+                Cell::text(c.kind()),
+                Cell::from_value(&json!(c.count)),
+                Cell::from_value(&json!(c.nulls)),
+                Cell::text(&distinct),
+                Cell::from_value(c.min.as_ref().unwrap_or(&Value::Null)),
+                Cell::from_value(c.max.as_ref().unwrap_or(&Value::Null)),
+                Cell::from_value(&round(c.mean())),
+                Cell::from_value(&round(c.stddev())),
+                Cell::text(&top),
+            ]
+        })
+        .collect();
+    let renderer = TableRenderer {
+        max_col_width: 40,
+        max_table_width: if io::stdout().is_terminal() {
+            terminal_size::terminal_size().map(|(w, _)| w.0 as usize)
+        } else {
+            None
+        },
+    };
+    print!("{}", renderer.render(&headers, &rows));
+    Ok(())
+}
+
+impl ColumnStats {
+    pub fn new(name: &str, distinct_limit: usize) -> Self {
+        Self {
+            name: name.to_string(),
+            count: 0,
+            nulls: 0,
+            all_bool: true,
+            all_int: true,
+            all_number: true,
+            min: None,
+            max: None,
+            mean: 0.0,
+            m2: 0.0,
+            numbers: 0,
+            counts: HashMap::new(),
+            hll: None,
+            distinct_limit,
+        }
+    }
+
+    pub fn update(&mut self, value: &Value) {
+        self.count += 1;
+        let value = match value {
+            Value::String(s) if s.is_empty() => Value::Null,
+            // 没有--infer时所有值都是字符串，这里再推断一次类型
+            Value::String(s) => infer_value(s),
+            v => v.clone(),
+        };
+        if value.is_null() {
+            self.nulls += 1;
+            return;
+        }
+        self.all_bool &= value.is_boolean();
+        self.all_int &= value.is_i64() || value.is_u64();
+        self.all_number &= value.is_number();
+
+        if let Some(n) = value.as_f64() {
+            self.numbers += 1;
+            let delta = n - self.mean;
+            self.mean += delta / self.numbers as f64;
+            self.m2 += delta * (n - self.mean);
+        }
+        if self
+            .min
+            .as_ref()
+            .is_none_or(|min| compare_values(&value, min) == Some(Ordering::Less))
+        {
+            self.min = Some(value.clone());
+        }
+        if self
+            .max
+            .as_ref()
+            .is_none_or(|max| compare_values(&value, max) == Some(Ordering::Greater))
+        {
+            self.max = Some(value.clone());
+        }
+
+        let cell = value_to_cell(&value);
+        match &mut self.hll {
+            None => {
+                *self.counts.entry(cell).or_insert(0) += 1;
+                if self.counts.len() > self.distinct_limit {
+                    let mut hll = HyperLogLog::new();
+                    self.counts.keys().for_each(|k| hll.insert(k));
+                    self.hll = Some(hll);
+                    self.prune();
+                }
+            }
+            Some(hll) => {
+                hll.insert(&cell);
+                // Misra-Gries：只保留有限个计数器，满了就整体减一，高频值会留下来
+                *self.counts.entry(cell).or_insert(0) += 1;
+                if self.counts.len() > self.distinct_limit {
+                    self.prune();
+                }
+            }
+        }
+    }
+
+    pub fn is_exact(&self) -> bool {
+        self.hll.is_none()
+    }
+
+    pub fn distinct(&self) -> u64 {
+        match &self.hll {
+            None => self.counts.len() as u64,
+            Some(hll) => hll.estimate(),
+        }
+    }
+
+    /// the k most frequent values, counts are lower bounds once the column went past the distinct limit
+    pub fn top(&self, k: usize) -> Vec<(&str, u64)> {
+        let mut top: Vec<(&str, u64)> = self.counts.iter().map(|(v, n)| (v.as_str(), *n)).collect();
+        top.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        top.truncate(k);
+        top
+    }
+
+    pub fn kind(&self) -> &'static str {
+        if self.count == self.nulls {
+            "empty"
+        } else if self.all_bool {
+            "boolean"
+        } else if self.all_int {
+            "integer"
+        } else if self.all_number {
+            "number"
+        } else {
+            "string"
+        }
+    }
+
+    pub fn mean(&self) -> Option<f64> {
+        (self.all_number && self.numbers > 0).then_some(self.mean)
+    }
+
+    /// sample standard deviation
+    pub fn stddev(&self) -> Option<f64> {
+        (self.all_number && self.numbers > 1).then(|| (self.m2 / (self.numbers - 1) as f64).sqrt())
+    }
+
+    fn prune(&mut self) {
+        for n in self.counts.values_mut() {
+            *n -= 1;
+        }
+        self.counts.retain(|_, n| *n > 0);
+    }
+
+    fn to_json(&self, top: usize) -> Value {
+        let top: Vec<Value> = self
+            .top(top)
+            .iter()
+            .map(|(v, n)| json!({ "value": v, "count": n }))
+            .collect();
+        json!({
+            "column": self.name,
+            "type": self.kind(),
+            "count": self.count,
+            "nulls": self.nulls,
+            "distinct": self.distinct(),
+            "distinct_exact": self.is_exact(),
+            "min": self.min,
+            "max": self.max,
+            "mean": self.mean(),
+            "stddev": self.stddev(),
+            "top": top,
+        })
+    }
+}
+
+fn round(n: Option<f64>) -> Value {
+    match n {
+        Some(n) => json!((n * 1000.0).round() / 1000.0),
+        None => Value::Null,
+    }
+}
+
+#[derive(Debug)]
+struct HyperLogLog {
+    registers: Vec<u8>,
+}
+
+impl HyperLogLog {
+    fn new() -> Self {
+        Self {
+            registers: vec![0; 1 << HLL_BITS],
+        }
+    }
+
+    fn insert(&mut self, value: &str) {
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        let hash = hasher.finish();
+        let index = (hash >> (64 - HLL_BITS)) as usize;
+        // 剩下的位中第一个1出现的位置
+        let rank = ((hash << HLL_BITS) | (1 << (HLL_BITS - 1))).leading_zeros() as u8 + 1;
+        self.registers[index] = self.registers[index].max(rank);
+    }
+
+    fn estimate(&self) -> u64 {
+        let m = self.registers.len() as f64;
+        let alpha = 0.7213 / (1.0 + 1.079 / m);
+        let sum: f64 = self.registers.iter().map(|&r| 2f64.powi(-(r as i32))).sum();
+        let estimate = alpha * m * m / sum;
+        let zeros = self.registers.iter().filter(|&&r| r == 0).count();
+        // 基数较小时用linear counting修正
+        if estimate <= 2.5 * m && zeros > 0 {
+            (m * (m / zeros as f64).ln()).round() as u64
+        } else {
+            estimate.round() as u64
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_column_stats() {
+        let mut stats = ColumnStats::new("kit", 100);
+        for v in ["1", "2", "2", "", "7"] {
+            stats.update(&json!(v));
+        }
+        assert_eq!(stats.kind(), "integer");
+        assert_eq!((stats.count, stats.nulls, stats.distinct()), (5, 1, 3));
+        assert_eq!(stats.min, Some(json!(1)));
+        assert_eq!(stats.max, Some(json!(7)));
+        assert_eq!(stats.mean(), Some(3.0));
+        assert!((stats.stddev().unwrap() - (22.0f64 / 3.0).sqrt()).abs() < 1e-9);
+        assert_eq!(stats.top(1), vec![("2", 2)]);
+    }
+
+    #[test]
+    fn test_distinct_switches_to_hyperloglog() {
+        let mut stats = ColumnStats::new("id", 1000);
+        for i in 0..50_000 {
+            stats.update(&json!(format!("id-{}", i % 20_000)));
+        }
+        assert!(!stats.is_exact());
+        let distinct = stats.distinct() as f64;
+        assert!(
+            (distinct - 20_000.0).abs() / 20_000.0 < 0.05,
+            "{}",
+            distinct
+        );
+    }
+}
@@ -0,0 +1,430 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Write as _,
+    io::{self, IsTerminal, Write},
+};
+
+use anyhow::Result;
+use serde_json::{json, Map, Value};
+
+use crate::cli::{CsvDialectOpts, CsvInputOpts, DiffFormat, OutputFormat};
+
+use super::{
+    csv_convert::{open_records, value_to_cell, RecordIter},
+    csv_writer::{output_writer, record_writer, WriterConfig},
+};
+
+const GREEN: &str = "\x1b[32m";
+const RED: &str = "\x1b[31m";
+const YELLOW: &str = "\x1b[33m";
+const RESET: &str = "\x1b[0m";
+
+/// the changes between two versions of a file, rows matched by their key columns
+#[derive(Debug, Clone, PartialEq)]
+pub struct CsvDiff {
+    pub key: Vec<String>,
+    /// columns of the new file, then the columns only the old file has
+    pub columns: Vec<String>,
+    pub added_columns: Vec<String>,
+    pub removed_columns: Vec<String>,
+    pub rows: Vec<RowChange>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RowChange {
+    pub kind: ChangeKind,
+    pub key: Vec<Value>,
+    /// the new row, or the old one when it was removed
+    pub row: Value,
+    /// changed cells of a modified row, in the columns both files have
+    pub cells: Vec<CellChange>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Modified,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CellChange {
+    pub column: String,
+    pub old: Value,
+    pub new: Value,
+}
+
+pub fn process_csv_diff(
+    old: &str,
+    new: &str,
+    dialect: &CsvDialectOpts,
+    key: &[String],
+    format: DiffFormat,
+    output: Option<&str>,
+) -> Result<()> {
+    // 两边都按字符串比较，不做类型推断，"1"和"1.0"算不同
+    let open = |input: &str| {
+        open_records(&CsvInputOpts {
+            input: input.to_string(),
+            input_format: None,
+            dialect: dialect.clone(),
+            infer: false,
+            types: vec![],
+        })
+    };
+    let diff = diff_records(open(old)?, open(new)?, key)?;
+
+    let mut writer = output_writer(output)?;
+    match format {
+        DiffFormat::Text => {
+            let color = output.is_none()
+                && io::stdout().is_terminal()
+                && std::env::var_os("NO_COLOR").is_none();
+            write!(writer, "{}", diff.render(color))?;
+        }
+        DiffFormat::Json => {
+            serde_json::to_writer_pretty(&mut writer, &diff.to_json())?;
+            writeln!(writer)?;
+        }
+        DiffFormat::Csv => {
+            let config = WriterConfig {
+                delimiter: dialect.output_delimiter(),
+                ..Default::default()
+            };
+            diff.write_patch(&mut writer, &config)?;
+        }
+    }
+    writer.finish()?;
+    Ok(())
+}
+
+/// match the rows of both sides by `key`. the old side is kept in memory, the new side is
+/// streamed. modified and added rows come in the order of the new file, removed rows last
+pub fn diff_records(
+    (old_headers, old_records): (Vec<String>, RecordIter),
+    (new_headers, new_records): (Vec<String>, RecordIter),
+    key: &[String],
+) -> Result<CsvDiff> {
+    for col in key {
+        if !old_headers.contains(col) || !new_headers.contains(col) {
+            anyhow::bail!("Unknown key column: {}", col);
+        }
+    }
+    let added_columns: Vec<String> = new_headers
+        .iter()
+        .filter(|h| !old_headers.contains(h))
+        .cloned()
+        .collect();
+    let removed_columns: Vec<String> = old_headers
+        .iter()
+        .filter(|h| !new_headers.contains(h))
+        .cloned()
+        .collect();
+    let common: Vec<&String> = new_headers
+        .iter()
+        .filter(|h| old_headers.contains(h))
+        .collect();
+
+    let mut old_rows = Vec::new();
+    let mut index = HashMap::new();
+    for record in old_records {
+        let record = record?;
+        let k = row_key(&record, key);
+        if index.insert(k.clone(), old_rows.len()).is_some() {
+            anyhow::bail!("Duplicate key in the old file: {}", k.join(", "));
+        }
+        old_rows.push(record);
+    }
+
+    let mut seen = vec![false; old_rows.len()];
+    let mut new_keys = HashSet::new();
+    let mut rows = Vec::new();
+    for record in new_records {
+        let record = record?;
+        let k = row_key(&record, key);
+        if !new_keys.insert(k.clone()) {
+            anyhow::bail!("Duplicate key in the new file: {}", k.join(", "));
+        }
+        match index.get(&k) {
+            Some(&i) => {
+                seen[i] = true;
+                let old = &old_rows[i];
+                let cells: Vec<CellChange> = common
+                    .iter()
+                    .filter_map(|col| {
+                        let old = old.get(*col).unwrap_or(&Value::Null);
+                        let new = record.get(*col).unwrap_or(&Value::Null);
+                        (old != new).then(|| CellChange {
+                            column: col.to_string(),
+                            old: old.clone(),
+                            new: new.clone(),
+                        })
+                    })
+                    .collect();
+                if !cells.is_empty() {
+                    rows.push(RowChange::new(ChangeKind::Modified, key, record, cells));
+                }
+            }
+            None => rows.push(RowChange::new(ChangeKind::Added, key, record, vec![])),
+        }
+    }
+    for (row, _) in old_rows.into_iter().zip(seen).filter(|(_, s)| !s) {
+        rows.push(RowChange::new(ChangeKind::Removed, key, row, vec![]));
+    }
+
+    let mut columns = new_headers;
+    columns.extend(removed_columns.iter().cloned());
+    Ok(CsvDiff {
+        key: key.to_vec(),
+        columns,
+        added_columns,
+        removed_columns,
+        rows,
+    })
+}
+
+fn row_key(record: &Value, key: &[String]) -> Vec<String> {
+    key.iter()
+        .map(|col| record.get(col).map(value_to_cell).unwrap_or_default())
+        .collect()
+}
+
+impl RowChange {
+    fn new(kind: ChangeKind, key: &[String], row: Value, cells: Vec<CellChange>) -> Self {
+        let key = key
+            .iter()
+            .map(|col| row.get(col).cloned().unwrap_or(Value::Null))
+            .collect();
+        Self {
+            kind,
+            key,
+            row,
+            cells,
+        }
+    }
+}
+
+impl ChangeKind {
+    fn name(&self) -> &'static str {
+        match self {
+            ChangeKind::Added => "added",
+            ChangeKind::Removed => "removed",
+            ChangeKind::Modified => "modified",
+        }
+    }
+}
+
+impl CsvDiff {
+    pub fn count(&self, kind: ChangeKind) -> usize {
+        self.rows.iter().filter(|r| r.kind == kind).count()
+    }
+
+    /// one line per added or removed row, a line per changed cell under modified rows
+    pub fn render(&self, color: bool) -> String {
+        let paint = |code: &str, s: &str| {
+            if color {
+                format!("{}{}{}", code, s, RESET)
+            } else {
+                s.to_string()
+            }
+        };
+        let mut out = String::new();
+        if !self.added_columns.is_empty() {
+            let line = format!("+ columns: {}", self.added_columns.join(", "));
+            let _ = writeln!(out, "{}", paint(GREEN, &line));
+        }
+        if !self.removed_columns.is_empty() {
+            let line = format!("- columns: {}", self.removed_columns.join(", "));
+            let _ = writeln!(out, "{}", paint(RED, &line));
+        }
+        for row in &self.rows {
+            let key = row
+                .key
+                .iter()
+                .map(value_to_cell)
+                .collect::<Vec<_>>()
+                .join(", ");
+            match row.kind {
+                ChangeKind::Modified => {
+                    let _ = writeln!(out, "{}", paint(YELLOW, &format!("~ {}", key)));
+                    for cell in &row.cells {
+                        let _ = writeln!(
+                            out,
+                            "    {}: {} -> {}",
+                            cell.column,
+                            paint(RED, &display(&cell.old)),
+                            paint(GREEN, &display(&cell.new))
+                        );
+                    }
+                }
+                kind => {
+                    let (sign, code) = if kind == ChangeKind::Added {
+                        ("+", GREEN)
+                    } else {
+                        ("-", RED)
+                    };
+                    let cells = self
+                        .columns
+                        .iter()
+                        .filter(|c| !self.key.contains(c))
+                        .filter_map(|c| row.row.get(c).map(|v| format!("{}={}", c, display(v))))
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    let line = format!("{} {}: {}", sign, key, cells);
+                    let _ = writeln!(out, "{}", paint(code, &line));
+                }
+            }
+        }
+        let _ = writeln!(
+            out,
+            "{} added, {} removed, {} modified",
+            self.count(ChangeKind::Added),
+            self.count(ChangeKind::Removed),
+            self.count(ChangeKind::Modified)
+        );
+        out
+    }
+
+    pub fn to_json(&self) -> Value {
+        let rows: Vec<Value> = self
+            .rows
+            .iter()
+            .map(|row| {
+                let key: Map<String, Value> = self
+                    .key
+                    .iter()
+                    .cloned()
+                    .zip(row.key.iter().cloned())
+                    .collect();
+                let mut change = json!({"change": row.kind.name(), "key": key});
+                if row.kind == ChangeKind::Modified {
+                    let cells: Map<String, Value> = row
+                        .cells
+                        .iter()
+                        .map(|c| (c.column.clone(), json!({"old": c.old, "new": c.new})))
+                        .collect();
+                    change["cells"] = Value::Object(cells);
+                } else {
+                    change["row"] = row.row.clone();
+                }
+                change
+            })
+            .collect();
+        json!({
+            "key": self.key,
+            "columns": {"added": self.added_columns, "removed": self.removed_columns},
+            "summary": {
+                "added": self.count(ChangeKind::Added),
+                "removed": self.count(ChangeKind::Removed),
+                "modified": self.count(ChangeKind::Modified),
+            },
+            "rows": rows,
+        })
+    }
+
+    /// a csv with a `_change` column and the full row: the new row for added and
+    /// modified rows, the old one for removed rows
+    pub fn write_patch(&self, writer: impl Write, config: &WriterConfig) -> Result<()> {
+        let mut columns = vec!["_change".to_string()];
+        columns.extend(self.columns.iter().cloned());
+        let mut writer = record_writer(OutputFormat::Csv, &columns, config, writer)?;
+        for row in &self.rows {
+            let mut record = Map::new();
+            record.insert("_change".to_string(), json!(row.kind.name()));
+            for col in &self.columns {
+                let value = row.row.get(col).cloned().unwrap_or(Value::Null);
+                record.insert(col.clone(), value);
+            }
+            writer.write_record(&Value::Object(record))?;
+        }
+        writer.finish()
+    }
+}
+
+// 空值显示成""，否则看不出是变成了空字符串
+fn display(value: &Value) -> String {
+    match value {
+        Value::Null => "null".to_string(),
+        Value::String(s) if s.is_empty() => "\"\"".to_string(),
+        v => value_to_cell(v),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn records(headers: &[&str], rows: &[&[&str]]) -> (Vec<String>, RecordIter) {
+        let headers: Vec<String> = headers.iter().map(|h| h.to_string()).collect();
+        let records: Vec<Result<Value>> = rows
+            .iter()
+            .map(|row| {
+                let map: Map<String, Value> = headers
+                    .iter()
+                    .cloned()
+                    .zip(row.iter().map(|c| json!(c)))
+                    .collect();
+                Ok(Value::Object(map))
+            })
+            .collect();
+        (headers, Box::new(records.into_iter()))
+    }
+
+    #[test]
+    fn test_diff_by_key() -> Result<()> {
+        let old = records(
+            &["Name", "Position", "Kit Number"],
+            &[
+                &["Gonzalo Higuain", "Forward", "21"],
+                &["Cristiano Ronaldo", "Forward", "7"],
+                &["Paulo Dybala", "Forward", "10"],
+            ],
+        );
+        // 行的顺序变了，Kit Number之外加了一列
+        let new = records(
+            &["Name", "Position", "Kit Number", "Nationality"],
+            &[
+                &["Paulo Dybala", "Forward", "10", "Argentina"],
+                &["Cristiano Ronaldo", "Forward", "77", "Portugal"],
+                &["Matthijs de Ligt", "Defender", "4", "Netherlands"],
+            ],
+        );
+        let diff = diff_records(old, new, &["Name".to_string()])?;
+        assert_eq!(diff.added_columns, vec!["Nationality"]);
+        let kinds: Vec<ChangeKind> = diff.rows.iter().map(|r| r.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![ChangeKind::Modified, ChangeKind::Added, ChangeKind::Removed]
+        );
+        assert_eq!(
+            diff.rows[0].cells,
+            vec![CellChange {
+                column: "Kit Number".to_string(),
+                old: json!("7"),
+                new: json!("77"),
+            }]
+        );
+        assert_eq!(
+            diff.render(false),
+            "+ columns: Nationality\n\
+             ~ Cristiano Ronaldo\n    Kit Number: 7 -> 77\n\
+             + Matthijs de Ligt: Position=Defender, Kit Number=4, Nationality=Netherlands\n\
+             - Gonzalo Higuain: Position=Forward, Kit Number=21\n\
+             1 added, 1 removed, 1 modified\n"
+        );
+
+        let mut patch = Vec::new();
+        diff.write_patch(&mut patch, &WriterConfig::default())?;
+        let patch = String::from_utf8(patch)?;
+        assert!(patch.starts_with("_change,Name,Position,Kit Number,Nationality\n"));
+        assert!(patch.contains("removed,Gonzalo Higuain,Forward,21,\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_duplicate_key() {
+        let old = records(&["Name"], &[&["Buffon"], &["Buffon"]]);
+        let new = records(&["Name"], &[]);
+        assert!(diff_records(old, new, &["Name".to_string()]).is_err());
+    }
+}
@@ -0,0 +1,100 @@
+use std::io::{Cursor, Read};
+
+use anyhow::Result;
+use chardetng::{EncodingDetector, Iso2022JpDetection, Utf8Detection};
+use encoding_rs::{Encoding, UTF_8};
+use encoding_rs_io::DecodeReaderBytesBuilder;
+
+use crate::cli::CsvEncoding;
+
+use super::compression::open_input;
+
+// 自动检测只看文件开头的一段
+const DETECT_SIZE: usize = 64 * 1024;
+
+/// open the input as a stream of UTF-8 bytes, transcoding from `encoding` or from
+/// the detected encoding when it is auto. a leading BOM is removed, compressed files are
+/// decompressed first
+pub fn utf8_reader(input: &str, encoding: CsvEncoding) -> Result<Box<dyn Read>> {
+    let mut file = open_input(input)?;
+    let mut sample = Vec::with_capacity(DETECT_SIZE);
+    (&mut file)
+        .take(DETECT_SIZE as u64)
+        .read_to_end(&mut sample)?;
+    let (encoding, bom_len) = match Encoding::for_bom(&sample) {
+        Some((bom_encoding, len)) => match encoding {
+            CsvEncoding::Label(e) if e != bom_encoding => (e, 0),
+            _ => (bom_encoding, len),
+        },
+        None => match encoding {
+            CsvEncoding::Label(e) => (e, 0),
+            CsvEncoding::Auto => (detect_encoding(&sample, sample.len() < DETECT_SIZE), 0),
+        },
+    };
+
+    sample.drain(..bom_len);
+    let rdr = Cursor::new(sample).chain(file);
+    if encoding == UTF_8 {
+        // utf-8不经过解码器，非法的字节序列留给后面按行报错，而不是被悄悄替换成U+FFFD
+        return Ok(Box::new(rdr));
+    }
+    let decoder = DecodeReaderBytesBuilder::new()
+        .encoding(Some(encoding))
+        .bom_sniffing(false)
+        .build(rdr);
+    Ok(Box::new(decoder))
+}
+
+/// guess the encoding of a sample without BOM. UTF-8 with only a few invalid sequences
+/// among many multibyte characters is still UTF-8, the bad rows are reported later
+pub fn detect_encoding(sample: &[u8], complete: bool) -> &'static Encoding {
+    let mut multibyte = 0;
+    let mut invalid = 0;
+    let mut chunks = sample.utf8_chunks().peekable();
+    while let Some(chunk) = chunks.next() {
+        multibyte += chunk.valid().chars().filter(|c| c.len_utf8() > 1).count();
+        // sample可能在一个多字节字符中间被截断，结尾处的不完整序列不算
+        let truncated = !complete && chunks.peek().is_none();
+        if !chunk.invalid().is_empty() && !truncated {
+            invalid += 1;
+        }
+    }
+    // 其他单字节编码的非ASCII字符几乎凑不成合法的utf-8多字节序列，
+    // 所以合法的多字节字符不比非法序列少时，按带了坏字节的utf-8处理
+    if multibyte >= invalid {
+        return UTF_8;
+    }
+    let mut detector = EncodingDetector::new(Iso2022JpDetection::Deny);
+    detector.feed(sample, complete);
+    detector.guess(None, Utf8Detection::Allow)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_encoding() {
+        let (gbk, _, _) = encoding_rs::GBK.encode("姓名,球队\n布冯,尤文图斯\n基耶利尼,尤文图斯\n");
+        assert_eq!(detect_encoding(&gbk, true).name(), "GBK");
+        let (sjis, _, _) = encoding_rs::SHIFT_JIS.encode("名前,チーム\n本田圭佑,ミラン\n");
+        assert_eq!(detect_encoding(&sjis, true), encoding_rs::SHIFT_JIS);
+        assert_eq!(detect_encoding("Dybala,10\n".as_bytes(), true), UTF_8);
+        // 截断在"尤"字中间
+        let utf8 = "布冯,尤".as_bytes();
+        assert_eq!(detect_encoding(&utf8[..utf8.len() - 1], false), UTF_8);
+    }
+
+    #[test]
+    fn test_detect_utf8_with_bad_byte() {
+        let mut sample = "Name,Nationality\nMiralem Pjanić,Bosnia\nGonzalo Higuaín,Argentina\n\
+            Blaise Matuidi,France\nWojciech Szczęsny,Poland\n"
+            .as_bytes()
+            .to_vec();
+        sample.extend_from_slice(b"Paulo Dybala,Argentin\xe1\n");
+        assert_eq!(detect_encoding(&sample, true), UTF_8);
+        let (latin1, _, _) =
+            encoding_rs::WINDOWS_1252.encode("Name\nGonzalo Higuaín\nDouglas Costa de Souza\n");
+        assert_eq!(detect_encoding(&latin1, true), encoding_rs::WINDOWS_1252);
+    }
+}
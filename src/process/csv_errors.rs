@@ -0,0 +1,160 @@
+use std::{fmt, fs::File};
+
+use anyhow::Result;
+use csv::{ByteRecord, StringRecord, Writer, WriterBuilder};
+
+use crate::cli::OnError;
+
+/// a row that could not be read or converted. other errors, e.g. IO errors, always stop the run
+#[derive(Debug, Clone, PartialEq)]
+pub struct RowError {
+    pub line: u64,
+    pub byte: u64,
+    pub reason: String,
+    /// the rejected row, re-encoded as a csv line
+    pub row: String,
+}
+
+/// apply the --on-error policy to the errors of a record stream
+pub struct RejectHandler {
+    policy: OnError,
+    path: String,
+    writer: Option<Writer<File>>,
+    pub rejected: u64,
+}
+
+impl RowError {
+    pub fn new(record: &ByteRecord, reason: String) -> Self {
+        let (line, byte) = record
+            .position()
+            .map(|p| (p.line(), p.byte()))
+            .unwrap_or_default();
+        let mut writer = WriterBuilder::new().flexible(true).from_writer(vec![]);
+        let row = writer
+            .write_byte_record(record)
+            .ok()
+            .and_then(|_| writer.into_inner().ok())
+            .map(|buf| String::from_utf8_lossy(&buf).trim_end().to_string())
+            .unwrap_or_default();
+        Self {
+            line,
+            byte,
+            reason,
+            row,
+        }
+    }
+}
+
+impl fmt::Display for RowError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}, byte {}: {}", self.line, self.byte, self.reason)
+    }
+}
+
+impl std::error::Error for RowError {}
+
+/// check the field count and the encoding of a raw record
+pub fn to_string_record(
+    headers: &StringRecord,
+    record: ByteRecord,
+) -> Result<StringRecord, RowError> {
+    if record.len() != headers.len() {
+        let reason = format!("expected {} fields, found {}", headers.len(), record.len());
+        return Err(RowError::new(&record, reason));
+    }
+    StringRecord::from_byte_record(record).map_err(|e| {
+        let field = e.utf8_error().field();
+        let reason = format!("invalid UTF-8 in field {}", field + 1);
+        RowError::new(&e.into_byte_record(), reason)
+    })
+}
+
+impl RejectHandler {
+    /// `path` is the sidecar file rejected rows are written to with `--on-error report`
+    pub fn new(policy: OnError, path: &str) -> Result<Self> {
+        let writer = match policy {
+            OnError::Report => {
+                let mut writer = Writer::from_path(path)?;
+                writer.write_record(["line", "byte", "reason", "row"])?;
+                Some(writer)
+            }
+            _ => None,
+        };
+        Ok(Self {
+            policy,
+            path: path.to_string(),
+            writer,
+            rejected: 0,
+        })
+    }
+
+    /// returns the error back when the run should stop
+    pub fn handle(&mut self, err: anyhow::Error) -> Result<()> {
+        let Some(row) = err.downcast_ref::<RowError>() else {
+            return Err(err);
+        };
+        match self.policy {
+            OnError::Fail => return Err(err),
+            OnError::Skip => {}
+            OnError::Report => {
+                if let Some(writer) = &mut self.writer {
+                    writer.write_record([
+                        row.line.to_string(),
+                        row.byte.to_string(),
+                        row.reason.clone(),
+                        row.row.clone(),
+                    ])?;
+                }
+            }
+        }
+        self.rejected += 1;
+        Ok(())
+    }
+
+    /// flush the sidecar file, and print a summary on stderr when rows were rejected
+    pub fn finish(&mut self, converted: u64) -> Result<()> {
+        if let Some(writer) = &mut self.writer {
+            writer.flush()?;
+        }
+        // --on-error fail遇到坏行直接报错，走到这里的rejected一定是skip或report留下的
+        if self.rejected == 0 {
+            return Ok(());
+        }
+        eprintln!("{} rows converted, {} rejected", converted, self.rejected);
+        if self.policy == OnError::Report {
+            eprintln!("rejected rows are written to {}", self.path);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reject_ragged_and_bad_utf8() -> Result<()> {
+        let data = b"name,kit\nDybala,10\nRonaldo\nBuffon,\xff1\n";
+        let mut reader = csv::ReaderBuilder::new()
+            .flexible(true)
+            .from_reader(&data[..]);
+        let headers = reader.headers()?.clone();
+        let results: Vec<_> = reader
+            .into_byte_records()
+            .map(|r| to_string_record(&headers, r.unwrap()))
+            .collect();
+        assert!(results[0].is_ok());
+        let ragged = results[1].clone().unwrap_err();
+        assert_eq!((ragged.line, ragged.byte), (3, 19));
+        assert_eq!(ragged.reason, "expected 2 fields, found 1");
+        assert_eq!(ragged.row, "Ronaldo");
+        let bad = results[2].clone().unwrap_err();
+        assert_eq!(bad.reason, "invalid UTF-8 in field 2");
+
+        let mut handler = RejectHandler::new(OnError::Skip, "unused.csv")?;
+        assert!(handler.handle(ragged.into()).is_ok());
+        assert!(handler.handle(anyhow::anyhow!("disk full")).is_err());
+        assert_eq!(handler.rejected, 1);
+        Ok(())
+    }
+}
@@ -0,0 +1,269 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::Result;
+use serde_json::{Map, Value};
+
+use crate::cli::{CsvInputOpts, CsvOutputOpts, JoinKind, Keep};
+
+use super::{
+    csv_convert::{open_records, open_records_skipping_unknown_types, value_to_cell},
+    csv_writer::output_record_writer,
+};
+
+/// join `right` onto the input by the key columns. the right file is read into memory,
+/// the left side is streamed
+pub fn process_csv_join(
+    left: &CsvInputOpts,
+    right: &str,
+    on: &[String],
+    how: JoinKind,
+    target: &CsvOutputOpts,
+) -> Result<()> {
+    // 右边的文件用和左边一样的读取选项，但格式按自己的扩展名判断
+    let right_opts = CsvInputOpts {
+        input: right.to_string(),
+        input_format: None,
+        ..left.clone()
+    };
+    // --type只作用于有这一列的那一边，两边都没有才报错
+    let (left_headers, left_records) = open_records_skipping_unknown_types(left)?;
+    let (right_headers, right_records) = open_records_skipping_unknown_types(&right_opts)?;
+    for o in &left.types {
+        if !left_headers.contains(&o.column) && !right_headers.contains(&o.column) {
+            anyhow::bail!("Unknown column in --type: {}", o.column);
+        }
+    }
+    check_columns(on, &left_headers)?;
+    check_columns(on, &right_headers)?;
+
+    let right_columns = right_columns(&left_headers, &right_headers, on);
+    let mut columns = left_headers.clone();
+    columns.extend(right_columns.iter().map(|(_, out)| out.clone()));
+
+    let right_rows = right_records.collect::<Result<Vec<_>>>()?;
+    let mut index: HashMap<Vec<String>, Vec<usize>> = HashMap::new();
+    for (i, row) in right_rows.iter().enumerate() {
+        if let Some(key) = join_key(row, on) {
+            index.entry(key).or_default().push(i);
+        }
+    }
+
+    let config = target.writer_config(&left.dialect);
+    let mut writer =
+        output_record_writer(target.format, &columns, &config, target.output.as_deref())?;
+    let mut matched = vec![false; right_rows.len()];
+    for record in left_records {
+        let record = record?;
+        let rows = join_key(&record, on).and_then(|key| index.get(&key));
+        match rows {
+            Some(rows) => {
+                for &i in rows {
+                    matched[i] = true;
+                    let joined = merge(&record, Some(&right_rows[i]), &right_columns);
+                    writer.write_record(&joined)?;
+                }
+            }
+            None if how != JoinKind::Inner => {
+                writer.write_record(&merge(&record, None, &right_columns))?;
+            }
+            None => {}
+        }
+    }
+
+    if how == JoinKind::Outer {
+        // 右边没有匹配上的行放在最后，左边的列除了key都是null
+        for (row, _) in right_rows.iter().zip(&matched).filter(|(_, m)| !**m) {
+            let mut left = Map::new();
+            for h in &left_headers {
+                let value = if on.contains(h) {
+                    row.get(h).cloned().unwrap_or(Value::Null)
+                } else {
+                    Value::Null
+                };
+                left.insert(h.clone(), value);
+            }
+            writer.write_record(&merge(&Value::Object(left), Some(row), &right_columns))?;
+        }
+    }
+    writer.finish()?;
+    Ok(())
+}
+
+/// drop rows whose key columns (the whole row when no key is given) were already seen
+pub fn process_csv_dedupe(
+    source: &CsvInputOpts,
+    key: &[String],
+    keep: Keep,
+    target: &CsvOutputOpts,
+) -> Result<()> {
+    let (headers, records) = open_records(source)?;
+    check_columns(key, &headers)?;
+    let key = if key.is_empty() { &headers } else { key };
+
+    let config = target.writer_config(&source.dialect);
+    let mut writer =
+        output_record_writer(target.format, &headers, &config, target.output.as_deref())?;
+    match keep {
+        Keep::First => {
+            // 保留第一条可以边读边写
+            let mut seen = HashSet::new();
+            for record in records {
+                let record = record?;
+                if seen.insert(dedupe_key(&record, key)) {
+                    writer.write_record(&record)?;
+                }
+            }
+        }
+        Keep::Last => {
+            let rows = records.collect::<Result<Vec<_>>>()?;
+            let mut last = HashMap::new();
+            for (i, row) in rows.iter().enumerate() {
+                last.insert(dedupe_key(row, key), i);
+            }
+            let keep: HashSet<usize> = last.into_values().collect();
+            for (i, row) in rows.iter().enumerate() {
+                if keep.contains(&i) {
+                    writer.write_record(row)?;
+                }
+            }
+        }
+    }
+    writer.finish()?;
+    Ok(())
+}
+
+fn check_columns(columns: &[String], headers: &[String]) -> Result<()> {
+    for col in columns {
+        if !headers.contains(col) {
+            anyhow::bail!("Unknown column: {}", col);
+        }
+    }
+    Ok(())
+}
+
+// 右边除key以外的列，和左边重名时加上_right后缀
+fn right_columns(left: &[String], right: &[String], on: &[String]) -> Vec<(String, String)> {
+    right
+        .iter()
+        .filter(|h| !on.contains(h))
+        .map(|h| {
+            let out = if left.contains(h) {
+                format!("{}_right", h)
+            } else {
+                h.clone()
+            };
+            (h.clone(), out)
+        })
+        .collect()
+}
+
+// 和SQL一样，key中有null的行不和任何行匹配
+fn join_key(record: &Value, on: &[String]) -> Option<Vec<String>> {
+    on.iter()
+        .map(|col| match record.get(col) {
+            None | Some(Value::Null) => None,
+            Some(v) => Some(value_to_cell(v)).filter(|s| !s.is_empty()),
+        })
+        .collect()
+}
+
+fn dedupe_key(record: &Value, key: &[String]) -> Vec<String> {
+    key.iter()
+        .map(|col| record.get(col).map(value_to_cell).unwrap_or_default())
+        .collect()
+}
+
+fn merge(left: &Value, right: Option<&Value>, right_columns: &[(String, String)]) -> Value {
+    let mut joined = left.as_object().cloned().unwrap_or_default();
+    for (name, out) in right_columns {
+        let value = right
+            .and_then(|r| r.get(name))
+            .cloned()
+            .unwrap_or(Value::Null);
+        joined.insert(out.clone(), value);
+    }
+    Value::Object(joined)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_join_helpers() {
+        let left = vec!["id".to_string(), "name".to_string()];
+        let right = vec!["id".to_string(), "name".to_string(), "team".to_string()];
+        let on = vec!["id".to_string()];
+        let columns = right_columns(&left, &right, &on);
+        assert_eq!(
+            columns,
+            vec![
+                ("name".to_string(), "name_right".to_string()),
+                ("team".to_string(), "team".to_string())
+            ]
+        );
+
+        let l = json!({"id": "1", "name": "Buffon"});
+        let r = json!({"id": "1", "name": "Gigi", "team": "Juventus"});
+        assert_eq!(join_key(&l, &on), Some(vec!["1".to_string()]));
+        assert_eq!(join_key(&json!({"id": ""}), &on), None);
+        assert_eq!(
+            merge(&l, Some(&r), &columns),
+            json!({"id": "1", "name": "Buffon", "name_right": "Gigi", "team": "Juventus"})
+        );
+        assert_eq!(
+            merge(&l, None, &columns),
+            json!({"id": "1", "name": "Buffon", "name_right": null, "team": null})
+        );
+    }
+
+    #[test]
+    fn test_join_type_overrides() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let left = dir.path().join("left.csv");
+        let right = dir.path().join("right.csv");
+        let output = dir.path().join("joined.json");
+        std::fs::write(&left, "Name,Kit Number\nBuffon,77\n")?;
+        std::fs::write(&right, "Name,Team\nBuffon,Juventus\n")?;
+        let join = |types: &str| -> Result<Value> {
+            let source = CsvInputOpts {
+                input: left.to_str().unwrap().to_string(),
+                input_format: None,
+                dialect: crate::cli::CsvDialectOpts {
+                    delimiter: crate::cli::CsvDelimiter::Char(b','),
+                    header: None,
+                    encoding: crate::cli::CsvEncoding::Auto,
+                },
+                infer: false,
+                types: vec![types.parse()?],
+            };
+            let target = CsvOutputOpts {
+                output: Some(output.to_str().unwrap().to_string()),
+                format: crate::cli::OutputFormat::Json,
+                root_name: "rows".into(),
+                row_name: "row".into(),
+            };
+            let on = vec!["Name".to_string()];
+            process_csv_join(
+                &source,
+                right.to_str().unwrap(),
+                &on,
+                JoinKind::Inner,
+                &target,
+            )?;
+            Ok(serde_json::from_str(&std::fs::read_to_string(&output)?)?)
+        };
+        // 只有左边有Kit Number，右边也不能因为它报错
+        assert_eq!(
+            join("Kit Number=int")?,
+            json!([{"Name": "Buffon", "Kit Number": 77, "Team": "Juventus"}])
+        );
+        assert_eq!(
+            join("Team=string")?,
+            json!([{"Name": "Buffon", "Kit Number": "77", "Team": "Juventus"}])
+        );
+        assert!(join("Club=string").is_err());
+        Ok(())
+    }
+}
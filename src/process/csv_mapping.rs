@@ -0,0 +1,285 @@
+use anyhow::Result;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use crate::{cli::Rename, get_content};
+
+use super::csv_convert::value_to_cell;
+
+/// pick, rename, reorder and derive columns before the records are serialized
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnMapper {
+    columns: Vec<MappedColumn>,
+}
+
+/// a mapping file, e.g.
+///
+/// ```yaml
+/// columns:
+///   - name: player
+///     from: Name
+///     transform: [trim, upper]
+///   - name: team
+///     value: Juventus
+///   - name: label
+///     concat: [Name, Kit Number]
+///     separator: " #"
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MappingFile {
+    pub columns: Vec<ColumnSpec>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ColumnSpec {
+    pub name: String,
+    /// source column, defaults to `name`
+    pub from: Option<String>,
+    /// constant value
+    pub value: Option<Value>,
+    /// join these columns with `separator`
+    pub concat: Option<Vec<String>>,
+    #[serde(default)]
+    pub separator: String,
+    #[serde(default)]
+    pub transform: Vec<Transform>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Transform {
+    Trim,
+    Upper,
+    Lower,
+    Title,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct MappedColumn {
+    name: String,
+    source: Source,
+    transforms: Vec<Transform>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Source {
+    Column(String),
+    Constant(Value),
+    Concat(Vec<String>, String),
+}
+
+impl ColumnMapper {
+    /// combine the options in this order: the mapping file, then --select, then --rename.
+    /// returns None when none of them is given
+    pub fn from_opts(
+        headers: &[String],
+        mapping: Option<&str>,
+        select: &[String],
+        rename: &[Rename],
+    ) -> Result<Option<Self>> {
+        if mapping.is_none() && select.is_empty() && rename.is_empty() {
+            return Ok(None);
+        }
+        let mut mapper = match mapping {
+            Some(path) => {
+                let file: MappingFile = serde_yaml::from_slice(&get_content(path)?)?;
+                Self::from_specs(headers, file.columns)?
+            }
+            None => Self::identity(headers),
+        };
+        if !select.is_empty() {
+            mapper.select(select)?;
+        }
+        for r in rename {
+            mapper.rename(&r.from, &r.to)?;
+        }
+        Ok(Some(mapper))
+    }
+
+    pub fn identity(headers: &[String]) -> Self {
+        let columns = headers
+            .iter()
+            .map(|h| MappedColumn {
+                name: h.clone(),
+                source: Source::Column(h.clone()),
+                transforms: vec![],
+            })
+            .collect();
+        Self { columns }
+    }
+
+    pub fn from_specs(headers: &[String], specs: Vec<ColumnSpec>) -> Result<Self> {
+        let check = |col: &str| -> Result<()> {
+            if !headers.iter().any(|h| h == col) {
+                anyhow::bail!("Unknown column in mapping: {}", col);
+            }
+            Ok(())
+        };
+        let mut columns = Vec::with_capacity(specs.len());
+        for spec in specs {
+            let source = match (spec.from, spec.value, spec.concat) {
+                (None, Some(value), None) => Source::Constant(value),
+                (None, None, Some(concat)) => {
+                    for col in &concat {
+                        check(col)?;
+                    }
+                    Source::Concat(concat, spec.separator)
+                }
+                (from, None, None) => {
+                    let from = from.unwrap_or_else(|| spec.name.clone());
+                    check(&from)?;
+                    Source::Column(from)
+                }
+                _ => anyhow::bail!(
+                    "Column {} in mapping: only one of from, value and concat is allowed",
+                    spec.name
+                ),
+            };
+            columns.push(MappedColumn {
+                name: spec.name,
+                source,
+                transforms: spec.transform,
+            });
+        }
+        Ok(Self { columns })
+    }
+
+    /// keep only these columns, in this order
+    pub fn select(&mut self, names: &[String]) -> Result<()> {
+        let mut columns = Vec::with_capacity(names.len());
+        for name in names {
+            let column = self
+                .columns
+                .iter()
+                .find(|c| &c.name == name)
+                .ok_or_else(|| anyhow::anyhow!("Unknown column in --select: {}", name))?;
+            columns.push(column.clone());
+        }
+        self.columns = columns;
+        Ok(())
+    }
+
+    pub fn rename(&mut self, from: &str, to: &str) -> Result<()> {
+        let column = self
+            .columns
+            .iter_mut()
+            .find(|c| c.name == from)
+            .ok_or_else(|| anyhow::anyhow!("Unknown column in --rename: {}", from))?;
+        column.name = to.to_string();
+        Ok(())
+    }
+
+    pub fn columns(&self) -> Vec<String> {
+        self.columns.iter().map(|c| c.name.clone()).collect()
+    }
+
+    pub fn apply(&self, record: &Value) -> Value {
+        let mut map = Map::with_capacity(self.columns.len());
+        for column in &self.columns {
+            let value = match &column.source {
+                Source::Column(col) => record.get(col).cloned().unwrap_or(Value::Null),
+                Source::Constant(value) => value.clone(),
+                Source::Concat(cols, separator) => Value::String(
+                    cols.iter()
+                        .map(|c| record.get(c).map(value_to_cell).unwrap_or_default())
+                        .collect::<Vec<_>>()
+                        .join(separator),
+                ),
+            };
+            let value = column.transforms.iter().fold(value, |v, t| t.apply(v));
+            map.insert(column.name.clone(), value);
+        }
+        Value::Object(map)
+    }
+}
+
+impl Transform {
+    // 只处理字符串，数字、布尔值等原样保留
+    fn apply(&self, value: Value) -> Value {
+        let Value::String(s) = value else {
+            return value;
+        };
+        Value::String(match self {
+            Transform::Trim => s.trim().to_string(),
+            Transform::Upper => s.to_uppercase(),
+            Transform::Lower => s.to_lowercase(),
+            Transform::Title => title_case(&s),
+        })
+    }
+}
+
+fn title_case(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut word_start = true;
+    for c in s.chars() {
+        if word_start {
+            out.extend(c.to_uppercase());
+        } else {
+            out.extend(c.to_lowercase());
+        }
+        word_start = !c.is_alphanumeric();
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_player_mapping() -> Result<()> {
+        let headers: Vec<String> = ["Name", "Position", "DOB", "Nationality", "Kit Number"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let mapper = ColumnMapper::from_opts(
+            &headers,
+            Some("assets/player_mapping.yaml"),
+            &[],
+            &["dob=born".parse()?],
+        )?
+        .unwrap();
+        assert_eq!(
+            mapper.columns(),
+            vec![
+                "name",
+                "position",
+                "born",
+                "nationality",
+                "kit",
+                "team",
+                "label"
+            ]
+        );
+        let record = json!({
+            "Name": " wojciech szczesny ",
+            "Position": "Goalkeeper",
+            "DOB": "Apr 18, 1990 (29)",
+            "Nationality": "Poland",
+            "Kit Number": 1,
+        });
+        assert_eq!(
+            mapper.apply(&record),
+            json!({
+                "name": "Wojciech Szczesny",
+                "position": "Goalkeeper",
+                "born": "Apr 18, 1990 (29)",
+                "nationality": "POLAND",
+                "kit": 1,
+                "team": "Juventus",
+                "label": " wojciech szczesny  #1",
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_select_unknown_column() {
+        let headers = vec!["Name".to_string()];
+        let result = ColumnMapper::from_opts(&headers, None, &["Kit".to_string()], &[]);
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,211 @@
+use anyhow::Result;
+use serde_json::{Map, Value};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+/// build nested objects and arrays from dotted keys, e.g. "address.city" and "tags[0]".
+/// null values are left out
+pub fn nest_record(record: &Value) -> Result<Value> {
+    let Some(obj) = record.as_object() else {
+        return Ok(record.clone());
+    };
+    let mut root = Value::Object(Map::new());
+    for (key, value) in obj {
+        if value.is_null() {
+            continue;
+        }
+        let path = checked_path(key, obj.len())?;
+        insert(&mut root, &path, value.clone())
+            .map_err(|e| anyhow::anyhow!("column {}: {}", key, e))?;
+    }
+    Ok(root)
+}
+
+/// the reverse of `nest_record`: nested objects and arrays become dotted keys.
+/// empty objects and arrays are kept as values
+pub fn flatten_record(record: &Value) -> Value {
+    let mut flat = Map::new();
+    match record {
+        Value::Object(obj) => {
+            for (key, value) in obj {
+                flatten_into(&mut flat, key.clone(), value);
+            }
+        }
+        v => return v.clone(),
+    }
+    Value::Object(flat)
+}
+
+/// top-level keys of the nested records, in the order of the headers. array indexes must
+/// be below the number of columns
+pub fn nested_columns(headers: &[String]) -> Result<Vec<String>> {
+    let mut columns: Vec<String> = Vec::new();
+    for header in headers {
+        let name = match checked_path(header, headers.len())?.into_iter().next() {
+            Some(Segment::Key(k)) => k,
+            _ => header.clone(),
+        };
+        if !columns.contains(&name) {
+            columns.push(name);
+        }
+    }
+    Ok(columns)
+}
+
+// 下标直接来自表头，不限制的话tags[4000000000]会分配几个G的数组；
+// 数组的元素个数不会超过列数，所以下标也不会
+fn checked_path(header: &str, columns: usize) -> Result<Vec<Segment>> {
+    let path = parse_path(header);
+    for segment in &path {
+        if let Segment::Index(i) = segment {
+            if *i >= columns {
+                anyhow::bail!(
+                    "column {}: array index {} is out of range, the file has {} columns",
+                    header,
+                    i,
+                    columns
+                );
+            }
+        }
+    }
+    Ok(path)
+}
+
+fn flatten_into(flat: &mut Map<String, Value>, prefix: String, value: &Value) {
+    match value {
+        Value::Object(obj) if !obj.is_empty() => {
+            for (key, value) in obj {
+                flatten_into(flat, format!("{}.{}", prefix, key), value);
+            }
+        }
+        Value::Array(arr) if !arr.is_empty() => {
+            for (i, value) in arr.iter().enumerate() {
+                flatten_into(flat, format!("{}[{}]", prefix, i), value);
+            }
+        }
+        v => {
+            flat.insert(prefix, v.clone());
+        }
+    }
+}
+
+// "a.b[0].c" => [Key(a), Key(b), Index(0), Key(c)]，不合法的[]按普通字符处理
+fn parse_path(path: &str) -> Vec<Segment> {
+    let mut segments = Vec::new();
+    for part in path.split('.') {
+        let (key, mut rest) = match part.find('[') {
+            Some(pos) => (&part[..pos], &part[pos..]),
+            None => (part, ""),
+        };
+        let mut indexes = Vec::new();
+        while let Some(index) = rest
+            .strip_prefix('[')
+            .and_then(|r| r.split_once(']'))
+            .and_then(|(n, r)| n.parse::<usize>().ok().map(|n| (n, r)))
+        {
+            indexes.push(Segment::Index(index.0));
+            rest = index.1;
+        }
+        if !rest.is_empty() {
+            segments.push(Segment::Key(part.to_string()));
+            continue;
+        }
+        if !key.is_empty() {
+            segments.push(Segment::Key(key.to_string()));
+        }
+        segments.extend(indexes);
+    }
+    segments
+}
+
+fn insert(node: &mut Value, path: &[Segment], value: Value) -> Result<()> {
+    let Some((first, rest)) = path.split_first() else {
+        *node = value;
+        return Ok(());
+    };
+    let child = match first {
+        Segment::Key(key) => {
+            if node.is_null() {
+                *node = Value::Object(Map::new());
+            }
+            let obj = node
+                .as_object_mut()
+                .ok_or_else(|| anyhow::anyhow!("expect an object at {}", key))?;
+            obj.entry(key.clone()).or_insert(Value::Null)
+        }
+        Segment::Index(i) => {
+            if node.is_null() {
+                *node = Value::Array(Vec::new());
+            }
+            let arr = node
+                .as_array_mut()
+                .ok_or_else(|| anyhow::anyhow!("expect an array at [{}]", i))?;
+            if arr.len() <= *i {
+                arr.resize(i + 1, Value::Null);
+            }
+            &mut arr[*i]
+        }
+    };
+    if rest.is_empty() && !child.is_null() {
+        anyhow::bail!("value is set twice");
+    }
+    insert(child, rest, value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_nest_and_flatten_round_trip() -> Result<()> {
+        let flat = json!({
+            "name": "Dybala",
+            "address.city": "Turin",
+            "address.zip": "10121",
+            "tags[0]": "forward",
+            "tags[1]": "captain",
+            "stats.goals[0].season": "2019",
+            "nickname": null,
+        });
+        let nested = nest_record(&flat)?;
+        assert_eq!(
+            nested,
+            json!({
+                "name": "Dybala",
+                "address": {"city": "Turin", "zip": "10121"},
+                "tags": ["forward", "captain"],
+                "stats": {"goals": [{"season": "2019"}]},
+            })
+        );
+        let mut expected = flat.as_object().unwrap().clone();
+        expected.remove("nickname");
+        assert_eq!(flatten_record(&nested), Value::Object(expected));
+        Ok(())
+    }
+
+    #[test]
+    fn test_nest_conflict() {
+        let flat = json!({"address": "Turin", "address.city": "Turin"});
+        assert!(nest_record(&flat).is_err());
+    }
+
+    #[test]
+    fn test_nested_columns() {
+        let headers: Vec<String> = ["name", "address.city", "address.zip", "tags[0]"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        assert_eq!(
+            nested_columns(&headers).unwrap(),
+            vec!["name", "address", "tags"]
+        );
+        let huge = vec!["name".to_string(), "tags[18446744073709551615]".to_string()];
+        assert!(nested_columns(&huge).is_err());
+        assert!(nest_record(&json!({"name": "Dybala", "tags[4000000000]": "x"})).is_err());
+    }
+}
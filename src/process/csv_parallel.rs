@@ -0,0 +1,457 @@
+use std::io::Read;
+
+use anyhow::Result;
+use csv::{ByteRecord, Position, StringRecord};
+use rayon::{prelude::*, ThreadPool, ThreadPoolBuilder};
+use serde_json::Value;
+
+use crate::cli::{CsvOpts, OnError, OutputFormat};
+
+use super::{
+    csv_convert::RecordPipeline,
+    csv_errors::{RejectHandler, RowError},
+    csv_reader::{open_csv_input, CsvDialect},
+    csv_types::ValueConverter,
+    csv_writer::{serialize_records, RecordWriter, WriterConfig},
+};
+
+// 每个任务转换的行数，以及每轮读入的任务数（乘以线程数）
+const CHUNK_ROWS: usize = 4096;
+const CHUNKS_PER_THREAD: usize = 4;
+const READ_SIZE: usize = 64 * 1024;
+
+/// converts csv input on a worker pool and writes it in the original order.
+/// the main thread only cuts the raw bytes at record boundaries, the workers parse,
+/// convert and serialize their chunk, each record going through exactly the same steps
+/// as in `converted_records`
+pub struct ParallelRecords {
+    splitter: RecordSplitter,
+    // 没有header时第一行已经被读出来了，要作为数据放回去
+    first: Option<RawChunk>,
+    chunks: ChunkConverter,
+    pool: ThreadPool,
+    chunk_rows: usize,
+}
+
+// worker里用到的所有东西，在线程之间共享
+struct ChunkConverter {
+    dialect: CsvDialect,
+    headers: StringRecord,
+    converter: ValueConverter,
+    pipeline: RecordPipeline,
+    columns: Vec<String>,
+    format: OutputFormat,
+    config: WriterConfig,
+    policy: OnError,
+}
+
+struct RawChunk {
+    data: Vec<u8>,
+    start: Position,
+}
+
+struct ConvertedChunk {
+    body: ChunkBody,
+    records: usize,
+    errors: Vec<anyhow::Error>,
+}
+
+// 文本格式在worker里就序列化好；二进制格式只能交给主线程的writer逐条写
+enum ChunkBody {
+    Bytes(Vec<u8>),
+    Records(Vec<Value>),
+}
+
+/// the parallel counterpart of `converted_records`, for csv input. 0 threads means one per cpu
+pub fn parallel_records(opts: &CsvOpts, threads: usize) -> Result<ParallelRecords> {
+    parallel_records_with(opts, threads, CHUNK_ROWS)
+}
+
+fn parallel_records_with(
+    opts: &CsvOpts,
+    threads: usize,
+    chunk_rows: usize,
+) -> Result<ParallelRecords> {
+    let source = &opts.source;
+    let (rdr, dialect) = open_csv_input(&source.input, &source.dialect)?;
+    // 坏行交给--on-error处理，和open_flexible_csv_reader一样
+    let dialect = CsvDialect {
+        flexible: true,
+        ..dialect
+    };
+    let mut splitter = RecordSplitter::new(rdr, &dialect);
+    let mut first = splitter.next_chunk(1)?;
+    let first_record = match &first {
+        Some(chunk) => parse_records(&dialect, chunk).next().transpose()?,
+        None => None,
+    }
+    .unwrap_or_default();
+    let headers = if dialect.has_headers {
+        first = None;
+        StringRecord::from_byte_record(first_record)
+            .map_err(|e| anyhow::anyhow!("invalid UTF-8 in the header: {}", e))?
+    } else {
+        (1..=first_record.len())
+            .map(|i| format!("column_{}", i))
+            .collect()
+    };
+
+    let converter = ValueConverter::new(&headers, source.infer, &source.types)?;
+    let names: Vec<String> = headers.iter().map(String::from).collect();
+    let (pipeline, columns) = RecordPipeline::new(opts, names, opts.nested)?;
+    let pool = ThreadPoolBuilder::new().num_threads(threads).build()?;
+    Ok(ParallelRecords {
+        splitter,
+        first,
+        chunks: ChunkConverter {
+            dialect,
+            headers,
+            converter,
+            pipeline,
+            columns,
+            format: opts.target.format,
+            config: opts.target.writer_config(&source.dialect),
+            policy: opts.on_error,
+        },
+        pool,
+        chunk_rows: chunk_rows.max(1),
+    })
+}
+
+impl ParallelRecords {
+    /// the columns of the converted records
+    pub fn columns(&self) -> &[String] {
+        &self.chunks.columns
+    }
+
+    /// convert everything and write it to `writer`, which was created for `columns`.
+    /// returns the number of converted records
+    pub fn write_to(
+        mut self,
+        writer: &mut dyn RecordWriter,
+        columns: &[String],
+        rejects: &mut RejectHandler,
+    ) -> Result<u64> {
+        self.chunks.columns = columns.to_vec();
+        let batch_chunks = self.pool.current_num_threads() * CHUNKS_PER_THREAD;
+        let mut converted = 0;
+        loop {
+            // 读入一轮数据，分块并行转换，再按原来的顺序写出去
+            let mut batch: Vec<RawChunk> = self.first.take().into_iter().collect();
+            let mut read_error = None;
+            while batch.len() < batch_chunks {
+                match self.splitter.next_chunk(self.chunk_rows) {
+                    Ok(Some(chunk)) => batch.push(chunk),
+                    Ok(None) => break,
+                    Err(e) => {
+                        // 和顺序读取一样，读错误之后不再继续
+                        read_error = Some(e);
+                        break;
+                    }
+                }
+            }
+            if batch.is_empty() && read_error.is_none() {
+                return Ok(converted);
+            }
+
+            let chunks = &self.chunks;
+            let converted_chunks: Vec<ConvertedChunk> = self
+                .pool
+                .install(|| batch.par_iter().map(|c| chunks.convert(c)).collect());
+            for chunk in converted_chunks {
+                match chunk.body {
+                    ChunkBody::Bytes(buf) => writer.write_chunk(&buf, chunk.records)?,
+                    ChunkBody::Records(records) => {
+                        for record in &records {
+                            writer.write_record(record)?;
+                        }
+                    }
+                }
+                converted += chunk.records as u64;
+                for e in chunk.errors {
+                    rejects.handle(e)?;
+                }
+            }
+            if let Some(e) = read_error {
+                return Err(e);
+            }
+        }
+    }
+}
+
+impl ChunkConverter {
+    fn convert(&self, chunk: &RawChunk) -> ConvertedChunk {
+        let mut records = Vec::new();
+        let mut errors = Vec::new();
+        for record in parse_records(&self.dialect, chunk) {
+            let result = record.and_then(|record| {
+                let value = self.converter.convert_raw(&self.headers, record)?;
+                self.pipeline.apply(value)
+            });
+            match result {
+                Ok(value) => records.push(value),
+                Err(e) => {
+                    // 之后的行要不要继续，和RejectHandler的判断一致
+                    let stop =
+                        self.policy == OnError::Fail || e.downcast_ref::<RowError>().is_none();
+                    errors.push(e);
+                    if stop {
+                        break;
+                    }
+                }
+            }
+        }
+        let count = records.len();
+        let body = if self.format.is_binary() {
+            ChunkBody::Records(records)
+        } else {
+            match serialize_records(self.format, &self.columns, &self.config, &records) {
+                Ok(buf) => ChunkBody::Bytes(buf),
+                Err(e) => {
+                    errors.insert(0, e);
+                    return ConvertedChunk {
+                        body: ChunkBody::Bytes(vec![]),
+                        records: 0,
+                        errors,
+                    };
+                }
+            }
+        };
+        ConvertedChunk {
+            body,
+            records: count,
+            errors,
+        }
+    }
+}
+
+// 块里的记录，位置换算成在整个文件里的位置，报错时的行号和顺序读取一样
+fn parse_records<'a>(
+    dialect: &CsvDialect,
+    chunk: &'a RawChunk,
+) -> impl Iterator<Item = Result<ByteRecord>> + 'a {
+    let reader = dialect
+        .reader_builder()
+        .has_headers(false)
+        .from_reader(&chunk.data[..]);
+    let start = chunk.start.clone();
+    reader.into_byte_records().map(move |record| {
+        let mut record = record?;
+        if let Some(pos) = record.position() {
+            let mut abs = Position::new();
+            abs.set_byte(start.byte() + pos.byte())
+                .set_line(start.line() + pos.line() - 1)
+                .set_record(start.record() + pos.record());
+            record.set_position(Some(abs));
+        }
+        Ok(record)
+    })
+}
+
+/// cuts the raw input into chunks of whole records. it follows the quoting rules of
+/// csv::Reader only as far as needed to tell a record terminator from a newline in a
+/// quoted field, the chunks are parsed by csv::Reader on the workers
+struct RecordSplitter {
+    reader: Box<dyn Read>,
+    delimiter: u8,
+    quote: u8,
+    state: SplitState,
+    has_content: bool,
+    // 读进来但还没切出去的字节，其中前scanned个已经扫描过
+    pending: Vec<u8>,
+    scanned: usize,
+    position: Position,
+    eof: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SplitState {
+    FieldStart,
+    InField,
+    InQuoted,
+    QuoteInQuoted,
+}
+
+impl RecordSplitter {
+    fn new(reader: Box<dyn Read>, dialect: &CsvDialect) -> Self {
+        let mut position = Position::new();
+        position.set_line(1);
+        Self {
+            reader,
+            delimiter: dialect.delimiter,
+            quote: dialect.quote,
+            state: SplitState::FieldStart,
+            has_content: false,
+            pending: Vec::new(),
+            scanned: 0,
+            position,
+            eof: false,
+        }
+    }
+
+    /// the next `rows` records, fewer at the end of the input
+    fn next_chunk(&mut self, rows: usize) -> Result<Option<RawChunk>> {
+        let mut found = 0;
+        let mut cut = None;
+        while cut.is_none() {
+            while self.scanned < self.pending.len() {
+                let b = self.pending[self.scanned];
+                self.scanned += 1;
+                if self.step(b) {
+                    found += 1;
+                    if found == rows {
+                        cut = Some(self.scanned);
+                        break;
+                    }
+                }
+            }
+            if cut.is_some() || self.eof {
+                break;
+            }
+            let len = self.pending.len();
+            self.pending.resize(len + READ_SIZE, 0);
+            let read = self.reader.read(&mut self.pending[len..]);
+            self.pending.truncate(len + *read.as_ref().unwrap_or(&0));
+            match read {
+                Ok(n) => self.eof = n == 0,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        // 文件末尾没有换行的最后一条记录
+        if cut.is_none() && self.has_content {
+            found += 1;
+            self.has_content = false;
+        }
+        let end = cut.unwrap_or(self.pending.len());
+        if end == 0 {
+            return Ok(None);
+        }
+        let data: Vec<u8> = self.pending.drain(..end).collect();
+        self.scanned -= end;
+        let start = self.position.clone();
+        let lines = data.iter().filter(|&&b| b == b'\n').count() as u64;
+        self.position
+            .set_byte(start.byte() + end as u64)
+            .set_line(start.line() + lines)
+            .set_record(start.record() + found as u64);
+        Ok(Some(RawChunk { data, start }))
+    }
+
+    // 返回true表示一条非空记录到这个字节结束
+    fn step(&mut self, b: u8) -> bool {
+        use SplitState::*;
+        let (state, end) = match (self.state, b) {
+            (InQuoted, b) if b == self.quote => (QuoteInQuoted, false),
+            (InQuoted, _) => (InQuoted, false),
+            (QuoteInQuoted, b) if b == self.quote => (InQuoted, false),
+            (_, b'\n' | b'\r') => (FieldStart, true),
+            (FieldStart, b) if b == self.quote => (InQuoted, false),
+            (_, b) if b == self.delimiter => (FieldStart, false),
+            _ => (InField, false),
+        };
+        self.state = state;
+        if !end {
+            self.has_content = true;
+            return false;
+        }
+        // 空行会被csv::Reader跳过，不算一条记录
+        std::mem::replace(&mut self.has_content, false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cli::CsvCommand,
+        process::{converted_records, record_writer, RecordIter},
+    };
+    use clap::Parser;
+
+    fn write_all(records: RecordIter, columns: &[String], format: OutputFormat) -> Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        let mut writer = record_writer(format, columns, &WriterConfig::default(), &mut buf)?;
+        for record in records {
+            writer.write_record(&record?)?;
+        }
+        writer.finish()?;
+        drop(writer);
+        Ok(buf)
+    }
+
+    fn write_parallel(records: ParallelRecords, format: OutputFormat) -> Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        let columns = records.columns().to_vec();
+        let mut writer = record_writer(format, &columns, &WriterConfig::default(), &mut buf)?;
+        let mut rejects = RejectHandler::new(OnError::Fail, "unused.csv")?;
+        records.write_to(writer.as_mut(), &columns, &mut rejects)?;
+        writer.finish()?;
+        drop(writer);
+        Ok(buf)
+    }
+
+    #[test]
+    fn test_parallel_output_is_identical() -> Result<()> {
+        let cmd = CsvCommand::try_parse_from([
+            "csv",
+            "-i",
+            "assets/juventus.csv",
+            "--infer",
+            "--type",
+            "DOB=date",
+            "--mapping",
+            "assets/player_mapping.yaml",
+        ])?;
+        let mut opts = cmd.convert;
+        for format in [
+            OutputFormat::Json,
+            OutputFormat::Csv,
+            OutputFormat::Xml,
+            OutputFormat::Toml,
+            OutputFormat::Yaml,
+        ] {
+            opts.target.format = format;
+            let (columns, records) = converted_records(&opts)?;
+            let expected = write_all(records, &columns, format)?;
+            let records = parallel_records_with(&opts, 3, 2)?;
+            assert_eq!(write_parallel(records, format)?, expected);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_at_record_boundaries() -> Result<()> {
+        let data = "a,b\r\n\"x\ny\",\"1\"\"\n2\"\n\nz,3\nw,\"4\"";
+        let dialect = CsvDialect::default();
+        let mut splitter = RecordSplitter::new(Box::new(data.as_bytes()), &dialect);
+        let mut chunks = Vec::new();
+        while let Some(chunk) = splitter.next_chunk(1)? {
+            chunks.push(chunk);
+        }
+        let texts: Vec<_> = chunks
+            .iter()
+            .map(|c| String::from_utf8_lossy(&c.data).to_string())
+            .collect();
+        assert_eq!(
+            texts,
+            vec!["a,b\r", "\n\"x\ny\",\"1\"\"\n2\"\n", "\nz,3\n", "w,\"4\""]
+        );
+        // 位置和csv::Reader在整个文件上读出来的一样
+        let positions: Vec<_> = chunks
+            .iter()
+            .flat_map(|c| parse_records(&dialect, c))
+            .map(|r| r.map(|r| r.position().map(|p| (p.byte(), p.line()))))
+            .collect::<Result<_>>()?;
+        let mut reader = dialect
+            .reader_builder()
+            .has_headers(false)
+            .from_reader(data.as_bytes());
+        let expected: Vec<_> = reader
+            .byte_records()
+            .map(|r| r.map(|r| r.position().map(|p| (p.byte(), p.line()))))
+            .collect::<Result<_, _>>()?;
+        assert_eq!(positions, expected);
+        Ok(())
+    }
+}
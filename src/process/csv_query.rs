@@ -0,0 +1,732 @@
+use std::{cmp::Ordering, collections::BinaryHeap, str::FromStr};
+
+use anyhow::Result;
+use serde_json::{Map, Value};
+
+use crate::cli::{CsvInputOpts, CsvOutputOpts, SortKey};
+
+use super::{
+    csv_convert::{open_records, value_to_cell},
+    csv_sort::compare_records,
+    csv_types::infer_value,
+    csv_writer::output_record_writer,
+};
+
+/// SELECT cols WHERE expr ORDER BY cols LIMIT n, every clause is optional
+#[derive(Debug, Clone, PartialEq)]
+pub struct Query {
+    /// empty means SELECT *
+    pub projection: Vec<SelectItem>,
+    pub filter: Option<Expr>,
+    pub order_by: Vec<SortKey>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SelectItem {
+    pub column: String,
+    pub alias: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Compare(Operand, CmpOp, Operand),
+    Like {
+        operand: Operand,
+        pattern: String,
+        negated: bool,
+    },
+    In {
+        operand: Operand,
+        list: Vec<Value>,
+        negated: bool,
+    },
+    IsNull {
+        operand: Operand,
+        negated: bool,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operand {
+    Column(String),
+    Literal(Value),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CmpOp {
+    Eq,
+    NotEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+}
+
+pub fn process_csv_query(source: &CsvInputOpts, target: &CsvOutputOpts, query: &str) -> Result<()> {
+    let query: Query = query.parse()?;
+    let (headers, records) = open_records(source)?;
+    query.validate(&headers)?;
+
+    let columns = query.output_columns(&headers);
+    let config = target.writer_config(&source.dialect);
+    let mut writer =
+        output_record_writer(target.format, &columns, &config, target.output.as_deref())?;
+
+    // 解析失败的记录要继续往下传，让错误从?处返回
+    let matched = records.filter(|r| match r {
+        Ok(record) => query.matches(record),
+        Err(_) => true,
+    });
+    let limit = query.limit.unwrap_or(usize::MAX);
+    if query.order_by.is_empty() {
+        // 没有ORDER BY时可以边读边写，读够LIMIT条就停止
+        for record in matched.take(limit) {
+            writer.write_record(&query.project(&record?))?;
+        }
+    } else if let Some(limit) = query.limit {
+        // 有LIMIT时只在堆里保留排在最前面的limit条，堆顶是其中最靠后的一条
+        let mut heap = BinaryHeap::with_capacity(limit.saturating_add(1).min(1024));
+        for (seq, record) in matched.enumerate() {
+            heap.push(Ranked {
+                query: &query,
+                seq,
+                record: record?,
+            });
+            if heap.len() > limit {
+                heap.pop();
+            }
+        }
+        for ranked in heap.into_sorted_vec() {
+            writer.write_record(&query.project(&ranked.record))?;
+        }
+    } else {
+        let mut rows = matched.collect::<Result<Vec<_>>>()?;
+        // sort_by是稳定排序，相同key保持原来的顺序
+        rows.sort_by(|a, b| query.compare(a, b));
+        for record in &rows {
+            writer.write_record(&query.project(record))?;
+        }
+    }
+    writer.finish()?;
+    Ok(())
+}
+
+// 堆里的一条记录，key相同时按读入的顺序，和稳定排序的结果一致
+struct Ranked<'a> {
+    query: &'a Query,
+    seq: usize,
+    record: Value,
+}
+
+impl Ord for Ranked<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.query
+            .compare(&self.record, &other.record)
+            .then(self.seq.cmp(&other.seq))
+    }
+}
+
+impl PartialOrd for Ranked<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Ranked<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Ranked<'_> {}
+
+impl Query {
+    /// every column the query refers to must exist in the file
+    pub fn validate(&self, headers: &[String]) -> Result<()> {
+        let mut columns: Vec<&str> = self.projection.iter().map(|s| s.column.as_str()).collect();
+        columns.extend(self.order_by.iter().map(|o| o.column.as_str()));
+        if let Some(filter) = &self.filter {
+            filter.columns(&mut columns);
+        }
+        for col in columns {
+            if !headers.iter().any(|h| h == col) {
+                anyhow::bail!("Unknown column: {}", col);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn output_columns(&self, headers: &[String]) -> Vec<String> {
+        if self.projection.is_empty() {
+            headers.to_vec()
+        } else {
+            self.projection
+                .iter()
+                .map(|s| s.alias.clone().unwrap_or_else(|| s.column.clone()))
+                .collect()
+        }
+    }
+
+    pub fn matches(&self, record: &Value) -> bool {
+        self.filter.as_ref().is_none_or(|f| f.eval(record))
+    }
+
+    pub fn project(&self, record: &Value) -> Value {
+        if self.projection.is_empty() {
+            return record.clone();
+        }
+        let mut map = Map::with_capacity(self.projection.len());
+        for item in &self.projection {
+            let value = record.get(&item.column).cloned().unwrap_or(Value::Null);
+            let name = item.alias.as_ref().unwrap_or(&item.column);
+            map.insert(name.clone(), value);
+        }
+        Value::Object(map)
+    }
+
+    pub fn compare(&self, a: &Value, b: &Value) -> Ordering {
+        compare_records(&self.order_by, a, b)
+    }
+}
+
+impl Expr {
+    pub fn eval(&self, record: &Value) -> bool {
+        match self {
+            Expr::And(a, b) => a.eval(record) && b.eval(record),
+            Expr::Or(a, b) => a.eval(record) || b.eval(record),
+            Expr::Not(e) => !e.eval(record),
+            Expr::Compare(left, op, right) => {
+                let (left, right) = (left.value(record), right.value(record));
+                let Some(ord) = compare_values(&left, &right) else {
+                    return false;
+                };
+                match op {
+                    CmpOp::Eq => ord == Ordering::Equal,
+                    CmpOp::NotEq => ord != Ordering::Equal,
+                    CmpOp::Lt => ord == Ordering::Less,
+                    CmpOp::LtEq => ord != Ordering::Greater,
+                    CmpOp::Gt => ord == Ordering::Greater,
+                    CmpOp::GtEq => ord != Ordering::Less,
+                }
+            }
+            Expr::Like {
+                operand,
+                pattern,
+                negated,
+            } => {
+                let value = operand.value(record);
+                !is_null(&value) && like(&value_to_cell(&value), pattern) != *negated
+            }
+            Expr::In {
+                operand,
+                list,
+                negated,
+            } => {
+                let value = operand.value(record);
+                if is_null(&value) {
+                    return false;
+                }
+                let found = list
+                    .iter()
+                    .any(|v| compare_values(&value, v) == Some(Ordering::Equal));
+                found != *negated
+            }
+            Expr::IsNull { operand, negated } => is_null(&operand.value(record)) != *negated,
+        }
+    }
+
+    fn columns<'a>(&'a self, out: &mut Vec<&'a str>) {
+        let mut push = |op: &'a Operand| {
+            if let Operand::Column(c) = op {
+                out.push(c);
+            }
+        };
+        match self {
+            Expr::And(a, b) | Expr::Or(a, b) => {
+                a.columns(out);
+                b.columns(out);
+            }
+            Expr::Not(e) => e.columns(out),
+            Expr::Compare(a, _, b) => {
+                push(a);
+                push(b);
+            }
+            Expr::Like { operand, .. }
+            | Expr::In { operand, .. }
+            | Expr::IsNull { operand, .. } => push(operand),
+        }
+    }
+}
+
+impl Operand {
+    fn value(&self, record: &Value) -> Value {
+        match self {
+            Operand::Column(c) => record.get(c).cloned().unwrap_or(Value::Null),
+            Operand::Literal(v) => v.clone(),
+        }
+    }
+}
+
+// 没开--infer时空单元格是""，也当作null
+pub(crate) fn is_null(value: &Value) -> bool {
+    match value {
+        Value::Null => true,
+        Value::String(s) => s.is_empty(),
+        _ => false,
+    }
+}
+
+/// numbers (and numeric strings) compare as numbers and sort before everything else, the
+/// rest compare as strings. this is a total order even for columns of mixed values
+pub fn compare_values(a: &Value, b: &Value) -> Option<Ordering> {
+    if is_null(a) || is_null(b) {
+        return None;
+    }
+    // 一对数字、一对字符串各比各的，数字和字符串之间不能再混着比，否则"2"<"10"<"1a"<"2"成环
+    Some(match (as_f64(a), as_f64(b)) {
+        (Some(x), Some(y)) => x.total_cmp(&y),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => value_to_cell(a).cmp(&value_to_cell(b)),
+    })
+}
+
+fn as_f64(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.trim().parse::<f64>().ok().filter(|f| f.is_finite()),
+        _ => None,
+    }
+}
+
+// SQL的LIKE，%匹配任意多个字符，_匹配一个字符，不区分大小写
+fn like(value: &str, pattern: &str) -> bool {
+    let value: Vec<char> = value.to_lowercase().chars().collect();
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let (mut v, mut p) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+    while v < value.len() {
+        if p < pattern.len() && (pattern[p] == '_' || pattern[p] == value[v]) {
+            v += 1;
+            p += 1;
+        } else if p < pattern.len() && pattern[p] == '%' {
+            backtrack = Some((p, v));
+            p += 1;
+        } else if let Some((bp, bv)) = backtrack {
+            p = bp + 1;
+            v = bv + 1;
+            backtrack = Some((bp, bv + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '%')
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    /// bare word, keywords are recognized case-insensitively later
+    Word(String),
+    /// "Kit Number" or `Kit Number`
+    QuotedIdent(String),
+    /// 'Goalkeeper'
+    Str(String),
+    Number(String),
+    Symbol(&'static str),
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>> {
+    let chars: Vec<char> = s.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            c if c.is_whitespace() => i += 1,
+            '\'' | '"' | '`' => {
+                let mut text = String::new();
+                i += 1;
+                loop {
+                    match chars.get(i) {
+                        None => anyhow::bail!("Unterminated quote in query"),
+                        // 两个连续的引号表示引号本身
+                        Some(&q) if q == c && chars.get(i + 1) == Some(&c) => {
+                            text.push(c);
+                            i += 2;
+                        }
+                        Some(&q) if q == c => {
+                            i += 1;
+                            break;
+                        }
+                        Some(&ch) => {
+                            text.push(ch);
+                            i += 1;
+                        }
+                    }
+                }
+                tokens.push(if c == '\'' {
+                    Token::Str(text)
+                } else {
+                    Token::QuotedIdent(text)
+                });
+            }
+            '(' | ')' | ',' | '*' | '=' => {
+                tokens.push(Token::Symbol(match c {
+                    '(' => "(",
+                    ')' => ")",
+                    ',' => ",",
+                    '*' => "*",
+                    _ => "=",
+                }));
+                i += 1;
+            }
+            '<' | '>' | '!' => {
+                let next = chars.get(i + 1).copied();
+                let (sym, len) = match (c, next) {
+                    ('<', Some('=')) => ("<=", 2),
+                    ('<', Some('>')) => ("!=", 2),
+                    ('>', Some('=')) => (">=", 2),
+                    ('!', Some('=')) => ("!=", 2),
+                    ('<', _) => ("<", 1),
+                    ('>', _) => (">", 1),
+                    _ => anyhow::bail!("Unexpected character in query: {}", c),
+                };
+                tokens.push(Token::Symbol(sym));
+                i += len;
+            }
+            c if c.is_ascii_digit()
+                || (c == '-' && chars.get(i + 1).is_some_and(|n| n.is_ascii_digit())) =>
+            {
+                let start = i;
+                i += 1;
+                while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                    i += 1;
+                }
+                tokens.push(Token::Number(chars[start..i].iter().collect()));
+            }
+            c if c.is_alphanumeric() || c == '_' => {
+                let start = i;
+                while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+                tokens.push(Token::Word(chars[start..i].iter().collect()));
+            }
+            c => anyhow::bail!("Unexpected character in query: {}", c),
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn is_keyword(&self, kw: &str) -> bool {
+        matches!(self.peek(), Some(Token::Word(w)) if w.eq_ignore_ascii_case(kw))
+    }
+
+    fn eat_keyword(&mut self, kw: &str) -> bool {
+        let ok = self.is_keyword(kw);
+        if ok {
+            self.pos += 1;
+        }
+        ok
+    }
+
+    fn expect_keyword(&mut self, kw: &str) -> Result<()> {
+        if !self.eat_keyword(kw) {
+            anyhow::bail!("Expected {} in query, found {:?}", kw, self.peek());
+        }
+        Ok(())
+    }
+
+    fn eat_symbol(&mut self, sym: &str) -> bool {
+        let ok = matches!(self.peek(), Some(Token::Symbol(s)) if *s == sym);
+        if ok {
+            self.pos += 1;
+        }
+        ok
+    }
+
+    fn expect_symbol(&mut self, sym: &str) -> Result<()> {
+        if !self.eat_symbol(sym) {
+            anyhow::bail!("Expected {} in query, found {:?}", sym, self.peek());
+        }
+        Ok(())
+    }
+
+    fn column(&mut self) -> Result<String> {
+        match self.next() {
+            Some(Token::Word(w)) | Some(Token::QuotedIdent(w)) => Ok(w),
+            t => anyhow::bail!("Expected column name in query, found {:?}", t),
+        }
+    }
+
+    fn query(&mut self) -> Result<Query> {
+        let mut projection = Vec::new();
+        if self.eat_keyword("SELECT") && !self.eat_symbol("*") {
+            loop {
+                let column = self.column()?;
+                let alias = if self.eat_keyword("AS") {
+                    Some(self.column()?)
+                } else {
+                    None
+                };
+                projection.push(SelectItem { column, alias });
+                if !self.eat_symbol(",") {
+                    break;
+                }
+            }
+        }
+        // FROM后面的表名没有意义，数据来自--input
+        if self.eat_keyword("FROM") {
+            self.column()?;
+        }
+        let filter = if self.eat_keyword("WHERE") {
+            Some(self.or_expr()?)
+        } else {
+            None
+        };
+        let mut order_by = Vec::new();
+        if self.eat_keyword("ORDER") {
+            self.expect_keyword("BY")?;
+            loop {
+                let column = self.column()?;
+                let desc = if self.eat_keyword("DESC") {
+                    true
+                } else {
+                    self.eat_keyword("ASC");
+                    false
+                };
+                order_by.push(SortKey { column, desc });
+                if !self.eat_symbol(",") {
+                    break;
+                }
+            }
+        }
+        let limit = if self.eat_keyword("LIMIT") {
+            match self.next() {
+                Some(Token::Number(n)) => Some(n.parse()?),
+                t => anyhow::bail!("Expected number after LIMIT, found {:?}", t),
+            }
+        } else {
+            None
+        };
+        if let Some(t) = self.peek() {
+            anyhow::bail!("Unexpected token in query: {:?}", t);
+        }
+        // ORDER BY可以用SELECT里的别名，换回原来的列名
+        for key in order_by.iter_mut() {
+            if let Some(item) = projection
+                .iter()
+                .find(|item| item.alias.as_ref() == Some(&key.column))
+            {
+                key.column = item.column.clone();
+            }
+        }
+        Ok(Query {
+            projection,
+            filter,
+            order_by,
+            limit,
+        })
+    }
+
+    fn or_expr(&mut self) -> Result<Expr> {
+        let mut left = self.and_expr()?;
+        while self.eat_keyword("OR") {
+            left = Expr::Or(Box::new(left), Box::new(self.and_expr()?));
+        }
+        Ok(left)
+    }
+
+    fn and_expr(&mut self) -> Result<Expr> {
+        let mut left = self.not_expr()?;
+        while self.eat_keyword("AND") {
+            left = Expr::And(Box::new(left), Box::new(self.not_expr()?));
+        }
+        Ok(left)
+    }
+
+    fn not_expr(&mut self) -> Result<Expr> {
+        if self.eat_keyword("NOT") {
+            return Ok(Expr::Not(Box::new(self.not_expr()?)));
+        }
+        if self.eat_symbol("(") {
+            let expr = self.or_expr()?;
+            self.expect_symbol(")")?;
+            return Ok(expr);
+        }
+        self.predicate()
+    }
+
+    fn predicate(&mut self) -> Result<Expr> {
+        let operand = self.operand()?;
+        if self.eat_keyword("IS") {
+            let negated = self.eat_keyword("NOT");
+            self.expect_keyword("NULL")?;
+            return Ok(Expr::IsNull { operand, negated });
+        }
+        let negated = self.eat_keyword("NOT");
+        if self.eat_keyword("LIKE") {
+            let pattern = match self.next() {
+                Some(Token::Str(s)) => s,
+                t => anyhow::bail!("Expected string after LIKE, found {:?}", t),
+            };
+            return Ok(Expr::Like {
+                operand,
+                pattern,
+                negated,
+            });
+        }
+        if self.eat_keyword("IN") {
+            self.expect_symbol("(")?;
+            let mut list = Vec::new();
+            loop {
+                match self.operand()? {
+                    Operand::Literal(v) => list.push(v),
+                    Operand::Column(c) => anyhow::bail!("Expected literal in IN list, found {}", c),
+                }
+                if !self.eat_symbol(",") {
+                    break;
+                }
+            }
+            self.expect_symbol(")")?;
+            return Ok(Expr::In {
+                operand,
+                list,
+                negated,
+            });
+        }
+        if negated {
+            anyhow::bail!("Expected LIKE or IN after NOT");
+        }
+        let op = match self.next() {
+            Some(Token::Symbol("=")) => CmpOp::Eq,
+            Some(Token::Symbol("!=")) => CmpOp::NotEq,
+            Some(Token::Symbol("<")) => CmpOp::Lt,
+            Some(Token::Symbol("<=")) => CmpOp::LtEq,
+            Some(Token::Symbol(">")) => CmpOp::Gt,
+            Some(Token::Symbol(">=")) => CmpOp::GtEq,
+            t => anyhow::bail!("Expected comparison operator, found {:?}", t),
+        };
+        Ok(Expr::Compare(operand, op, self.operand()?))
+    }
+
+    fn operand(&mut self) -> Result<Operand> {
+        let operand = match self.next() {
+            Some(Token::Str(s)) => Operand::Literal(Value::String(s)),
+            Some(Token::Number(n)) => Operand::Literal(infer_value(&n)),
+            Some(Token::QuotedIdent(c)) => Operand::Column(c),
+            Some(Token::Word(w)) if w.eq_ignore_ascii_case("NULL") => Operand::Literal(Value::Null),
+            Some(Token::Word(w)) if w.eq_ignore_ascii_case("TRUE") => {
+                Operand::Literal(Value::Bool(true))
+            }
+            Some(Token::Word(w)) if w.eq_ignore_ascii_case("FALSE") => {
+                Operand::Literal(Value::Bool(false))
+            }
+            Some(Token::Word(w)) => Operand::Column(w),
+            t => anyhow::bail!("Expected column or value, found {:?}", t),
+        };
+        Ok(operand)
+    }
+}
+
+impl FromStr for Query {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let tokens = tokenize(s)?;
+        Parser { tokens, pos: 0 }.query()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_query() -> Result<()> {
+        let q: Query = "select Name, \"Kit Number\" as kit where Position = 'Goalkeeper' and (\"Kit Number\" > 10 or Name like '%buffon') order by kit desc limit 2".parse()?;
+        assert_eq!(q.output_columns(&[]), vec!["Name", "kit"]);
+        assert_eq!(q.limit, Some(2));
+        assert!(matches!(q.filter, Some(Expr::And(_, _))));
+        assert_eq!(q.order_by[0].column, "Kit Number");
+        assert!(q
+            .validate(&["Name".into(), "Position".into(), "Kit Number".into()])
+            .is_ok());
+        assert!(q.validate(&["Name".into(), "Position".into()]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_eval_filter() -> Result<()> {
+        let record =
+            json!({"Name": "Gianluigi Buffon", "Position": "Goalkeeper", "Kit Number": "77"});
+        let matches = |q: &str| -> Result<bool> { Ok(q.parse::<Query>()?.matches(&record)) };
+        assert!(matches("WHERE \"Kit Number\" > 9")?);
+        assert!(matches("WHERE `Kit Number` IN (1, 77)")?);
+        assert!(matches("WHERE Name LIKE 'gian%b_ffon'")?);
+        assert!(matches(
+            "WHERE Position != 'Defender' AND NOT Name IS NULL"
+        )?);
+        assert!(!matches("WHERE Position NOT IN ('Goalkeeper')")?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_query_juventus() -> Result<()> {
+        let source = CsvInputOpts {
+            input: "assets/juventus.csv".into(),
+            input_format: None,
+            dialect: crate::cli::CsvDialectOpts {
+                delimiter: crate::cli::CsvDelimiter::Char(b','),
+                header: None,
+                encoding: crate::cli::CsvEncoding::Auto,
+            },
+            infer: true,
+            types: vec![],
+        };
+        let dir = tempfile::tempdir()?;
+        let output = dir.path().join("keepers.json");
+        let target = CsvOutputOpts {
+            output: Some(output.to_str().unwrap().to_string()),
+            format: crate::cli::OutputFormat::Json,
+            root_name: "rows".into(),
+            row_name: "row".into(),
+        };
+        let query = |q: &str| -> Result<Value> {
+            process_csv_query(&source, &target, q)?;
+            Ok(serde_json::from_str(&std::fs::read_to_string(&output)?)?)
+        };
+        assert_eq!(
+            query("SELECT Name, \"Kit Number\" AS kit WHERE Position = 'Goalkeeper' ORDER BY kit DESC LIMIT 2")?,
+            json!([
+                {"Name": "Gianluigi Buffon", "kit": 77},
+                {"Name": "Mattia Perin", "kit": 37}
+            ])
+        );
+        // 没有LIMIT时整个排序，结果要和堆里取前几条一致
+        let all = query("SELECT Name WHERE Position = 'Goalkeeper' ORDER BY \"Kit Number\" DESC")?;
+        assert_eq!(all.as_array().map(Vec::len), Some(4));
+        assert_eq!(all[0], json!({"Name": "Gianluigi Buffon"}));
+        Ok(())
+    }
+}
@@ -0,0 +1,310 @@
+use std::io::{Cursor, Read};
+
+use anyhow::Result;
+use csv::{Reader, ReaderBuilder, StringRecord};
+
+use crate::cli::{CsvDelimiter, CsvDialectOpts};
+
+use super::csv_encoding::utf8_reader;
+
+// 嗅探只看文件开头的几KB
+const SNIFF_SIZE: usize = 8 * 1024;
+const DELIMITER_CANDIDATES: &[u8] = b",;\t|:";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CsvDialect {
+    pub delimiter: u8,
+    pub quote: u8,
+    pub has_headers: bool,
+    /// allow rows with a different number of fields than the header
+    pub flexible: bool,
+}
+
+impl Default for CsvDialect {
+    fn default() -> Self {
+        Self {
+            delimiter: b',',
+            quote: b'"',
+            has_headers: true,
+            flexible: false,
+        }
+    }
+}
+
+impl CsvDialect {
+    /// guess delimiter, quote character and header presence from a sample of the file
+    pub fn sniff(sample: &[u8]) -> Self {
+        let quote = sniff_quote(sample);
+        let delimiter = sniff_delimiter(sample, quote);
+        let records = sample_records(sample, delimiter, quote);
+        let has_headers = sniff_has_headers(&records);
+        Self {
+            delimiter,
+            quote,
+            has_headers,
+            flexible: false,
+        }
+    }
+
+    /// resolve the dialect from the command line options, sniffing the sample only when needed
+    pub fn from_opts(opts: &CsvDialectOpts, sample: &[u8]) -> Self {
+        match opts.delimiter {
+            CsvDelimiter::Auto => {
+                let mut dialect = Self::sniff(sample);
+                if let Some(header) = opts.header {
+                    dialect.has_headers = header;
+                }
+                dialect
+            }
+            CsvDelimiter::Char(delimiter) => Self {
+                delimiter,
+                quote: b'"',
+                has_headers: opts.header.unwrap_or(true),
+                flexible: false,
+            },
+        }
+    }
+
+    pub fn reader_builder(&self) -> ReaderBuilder {
+        let mut builder = ReaderBuilder::new();
+        builder
+            .delimiter(self.delimiter)
+            .quote(self.quote)
+            .has_headers(self.has_headers)
+            .flexible(self.flexible);
+        builder
+    }
+}
+
+/// open a CSV file with the dialect from the options, returns the reader together with the headers.
+/// when the file has no header row, the headers are generated as column_1..N
+pub fn open_csv_reader(
+    input: &str,
+    opts: &CsvDialectOpts,
+) -> Result<(Reader<Box<dyn Read>>, StringRecord)> {
+    open_with(input, opts, false)
+}
+
+/// like `open_csv_reader`, but ragged rows are returned instead of failing,
+/// so the caller can decide what to do with them
+pub fn open_flexible_csv_reader(
+    input: &str,
+    opts: &CsvDialectOpts,
+) -> Result<(Reader<Box<dyn Read>>, StringRecord)> {
+    open_with(input, opts, true)
+}
+
+fn open_with(
+    input: &str,
+    opts: &CsvDialectOpts,
+    flexible: bool,
+) -> Result<(Reader<Box<dyn Read>>, StringRecord)> {
+    let (rdr, dialect) = open_csv_input(input, opts)?;
+    let dialect = CsvDialect {
+        flexible,
+        ..dialect
+    };
+    csv_reader_from(rdr, &dialect)
+}
+
+/// the utf-8 input from its start, together with the dialect from the options,
+/// for callers that split the raw bytes themselves
+pub fn open_csv_input(input: &str, opts: &CsvDialectOpts) -> Result<(Box<dyn Read>, CsvDialect)> {
+    let mut file = utf8_reader(input, opts.encoding)?;
+    let mut sample = Vec::with_capacity(SNIFF_SIZE);
+    (&mut file)
+        .take(SNIFF_SIZE as u64)
+        .read_to_end(&mut sample)?;
+    let dialect = CsvDialect::from_opts(opts, &sample);
+    // 已经读出来的sample要再接回去，否则reader会丢掉文件开头
+    let rdr: Box<dyn Read> = Box::new(Cursor::new(sample).chain(file));
+    Ok((rdr, dialect))
+}
+
+pub fn csv_reader_from(
+    rdr: Box<dyn Read>,
+    dialect: &CsvDialect,
+) -> Result<(Reader<Box<dyn Read>>, StringRecord)> {
+    let mut reader = dialect.reader_builder().from_reader(rdr);
+    let headers = if dialect.has_headers {
+        reader.headers()?.clone()
+    } else {
+        // has_headers为false时，headers()返回的是第一行，但这一行仍然会在records()中出现
+        let len = reader.headers()?.len();
+        (1..=len).map(|i| format!("column_{}", i)).collect()
+    };
+    Ok((reader, headers))
+}
+
+fn sniff_quote(sample: &[u8]) -> u8 {
+    let mut double = 0;
+    let mut single = 0;
+    let mut at_field_start = true;
+    for &b in sample {
+        if at_field_start {
+            match b {
+                b'"' => double += 1,
+                b'\'' => single += 1,
+                _ => {}
+            }
+        }
+        at_field_start = b == b'\n' || DELIMITER_CANDIDATES.contains(&b);
+    }
+    if single > double {
+        b'\''
+    } else {
+        b'"'
+    }
+}
+
+fn sniff_delimiter(sample: &[u8], quote: u8) -> u8 {
+    let mut best = (0.0, 0, b',');
+    for &delimiter in DELIMITER_CANDIDATES {
+        let counts: Vec<usize> = sample_records(sample, delimiter, quote)
+            .iter()
+            .map(|r| r.len())
+            .collect();
+        if counts.is_empty() {
+            continue;
+        }
+        let mode = mode(&counts);
+        if mode < 2 {
+            continue;
+        }
+        let consistency =
+            counts.iter().filter(|&&c| c == mode).count() as f64 / counts.len() as f64;
+        if consistency > best.0 || (consistency == best.0 && mode > best.1) {
+            best = (consistency, mode, delimiter);
+        }
+    }
+    best.2
+}
+
+fn sample_records(sample: &[u8], delimiter: u8, quote: u8) -> Vec<StringRecord> {
+    // sample可能在一行中间被截断，最后一行不完整时丢掉
+    let sample = if sample.len() >= SNIFF_SIZE {
+        match sample.iter().rposition(|&b| b == b'\n') {
+            Some(pos) => &sample[..=pos],
+            None => sample,
+        }
+    } else {
+        sample
+    };
+    ReaderBuilder::new()
+        .delimiter(delimiter)
+        .quote(quote)
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(sample)
+        .records()
+        .map_while(|r| r.ok())
+        .collect()
+}
+
+fn mode(counts: &[usize]) -> usize {
+    let mut freq = std::collections::HashMap::new();
+    for &c in counts {
+        *freq.entry(c).or_insert(0) += 1;
+    }
+    freq.into_iter()
+        .max_by_key(|&(c, n)| (n, c))
+        .map(|(c, _)| c)
+        .unwrap_or(0)
+}
+
+#[derive(Debug, PartialEq)]
+enum CellKind {
+    Number,
+    Length(usize),
+}
+
+// 和python的csv.Sniffer类似：数据行某一列类型一致时，如果第一行这一列的类型不同，就投票认为有header
+fn sniff_has_headers(records: &[StringRecord]) -> bool {
+    let Some((header, rows)) = records.split_first() else {
+        return true;
+    };
+    if rows.is_empty() {
+        return true;
+    }
+    let mut votes = 0;
+    for (i, cell) in header.iter().enumerate() {
+        let mut kind = None;
+        let mut consistent = true;
+        for row in rows {
+            let Some(value) = row.get(i) else {
+                continue;
+            };
+            let k = cell_kind(value);
+            match &kind {
+                None => kind = Some(k),
+                Some(prev) if *prev != k => {
+                    consistent = false;
+                    break;
+                }
+                _ => {}
+            }
+        }
+        match kind {
+            Some(kind) if consistent => {
+                if cell_kind(cell) == kind {
+                    votes -= 1;
+                } else {
+                    votes += 1;
+                }
+            }
+            _ => {}
+        }
+    }
+    votes > 0
+}
+
+fn cell_kind(value: &str) -> CellKind {
+    if value.trim().parse::<f64>().is_ok() {
+        CellKind::Number
+    } else {
+        CellKind::Length(value.chars().count())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::CsvEncoding;
+
+    #[test]
+    fn test_sniff_semicolon_with_header() {
+        let sample = b"name;age;city\nalice;30;\"Paris; France\"\nbob;25;Rome\n";
+        let dialect = CsvDialect::sniff(sample);
+        assert_eq!(dialect.delimiter, b';');
+        assert_eq!(dialect.quote, b'"');
+        assert!(dialect.has_headers);
+    }
+
+    #[test]
+    fn test_sniff_tab_without_header() -> Result<()> {
+        let sample = b"alice\t30\nbob\t25\ncarol\t41\n";
+        let dialect = CsvDialect::sniff(sample);
+        assert_eq!(dialect.delimiter, b'\t');
+        assert!(!dialect.has_headers);
+
+        let (mut reader, headers) = csv_reader_from(Box::new(&sample[..]), &dialect)?;
+        assert_eq!(headers, vec!["column_1", "column_2"]);
+        assert_eq!(reader.records().count(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_sniff_juventus() -> Result<()> {
+        let opts = CsvDialectOpts {
+            delimiter: CsvDelimiter::Auto,
+            header: None,
+            encoding: CsvEncoding::Auto,
+        };
+        let (_, headers) = open_csv_reader("assets/juventus.csv", &opts)?;
+        assert_eq!(
+            headers,
+            vec!["Name", "Position", "DOB", "Nationality", "Kit Number"]
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,434 @@
+use std::{collections::HashSet, path::Path};
+
+use anyhow::Result;
+use serde_json::{json, Map, Value};
+
+use crate::{cli::CsvDialectOpts, get_content};
+
+use super::{
+    csv_reader::{open_csv_reader, open_flexible_csv_reader},
+    csv_types::{detect_date_format, infer_value, parse_bool, parse_date_with},
+};
+
+const ISO_DATE: &str = "%Y-%m-%d";
+
+/// one problem found by `rcli csv validate`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Violation {
+    pub line: u64,
+    pub column: String,
+    pub message: String,
+}
+
+// 推断过程中每一列的统计
+#[derive(Debug)]
+struct ColumnInference {
+    count: usize,
+    nulls: usize,
+    all_bool: bool,
+    all_int: bool,
+    all_number: bool,
+    // None表示还没见过非空值，Some(None)表示不是日期
+    date_format: Option<Option<&'static str>>,
+    // 超过max_enum个不同的值之后就不再记录
+    distinct: Option<Vec<String>>,
+    seen: HashSet<String>,
+}
+
+/// infer a JSON Schema (draft 2020-12) from a CSV file: column types, nullability,
+/// enum candidates for low-cardinality columns and date formats
+pub fn process_csv_schema(input: &str, dialect: &CsvDialectOpts, max_enum: usize) -> Result<Value> {
+    let (mut reader, headers) = open_csv_reader(input, dialect)?;
+    let mut columns: Vec<ColumnInference> =
+        headers.iter().map(|_| ColumnInference::new()).collect();
+    for result in reader.records() {
+        let record = result?;
+        for (col, cell) in columns.iter_mut().zip(record.iter()) {
+            col.update(cell, max_enum);
+        }
+    }
+
+    let title = Path::new(input)
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("csv");
+    let mut properties = Map::new();
+    for (name, col) in headers.iter().zip(&columns) {
+        properties.insert(name.to_string(), col.to_schema());
+    }
+    Ok(json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": title,
+        "type": "object",
+        "properties": properties,
+        "required": headers.iter().collect::<Vec<_>>(),
+        "additionalProperties": false,
+    }))
+}
+
+/// check every row of a CSV file against a schema, returns all the violations found.
+/// supports the subset of JSON Schema that `process_csv_schema` generates, plus
+/// minimum/maximum and minLength/maxLength
+pub fn process_csv_validate(
+    input: &str,
+    dialect: &CsvDialectOpts,
+    schema: &str,
+) -> Result<Vec<Violation>> {
+    // yaml是json的超集，两种格式的schema都能解析
+    let schema: Value = serde_yaml::from_slice(&get_content(schema)?)?;
+    let properties = schema
+        .get("properties")
+        .and_then(|p| p.as_object())
+        .ok_or_else(|| anyhow::anyhow!("Schema has no properties"))?;
+    let additional = schema
+        .get("additionalProperties")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(true);
+
+    // 字段数不对的行也当成违规报告出来，不能让第一行就中止整个检查
+    let (mut reader, mut headers) = open_flexible_csv_reader(input, dialect)?;
+    let mut violations = Vec::new();
+    if !reader.has_headers() {
+        // 没有header行就没有列名可查，各列按顺序对应schema里的属性
+        headers = headers
+            .iter()
+            .enumerate()
+            .map(|(i, h)| properties.keys().nth(i).map_or(h, String::as_str))
+            .collect();
+    }
+    let rules: Vec<Option<&Value>> = headers.iter().map(|h| properties.get(h)).collect();
+
+    if reader.has_headers() {
+        let header_line = 1;
+        if let Some(required) = schema.get("required").and_then(|r| r.as_array()) {
+            for name in required.iter().filter_map(|n| n.as_str()) {
+                if !headers.iter().any(|h| h == name) {
+                    violations.push(Violation::new(
+                        header_line,
+                        name,
+                        "required column is missing",
+                    ));
+                }
+            }
+        }
+        if !additional {
+            for (h, rule) in headers.iter().zip(&rules) {
+                if rule.is_none() {
+                    violations.push(Violation::new(
+                        header_line,
+                        h,
+                        "column is not in the schema",
+                    ));
+                }
+            }
+        }
+    }
+
+    for result in reader.records() {
+        let record = result?;
+        let line = record.position().map(|p| p.line()).unwrap_or_default();
+        if record.len() != headers.len() {
+            let column = match headers.get(record.len()) {
+                Some(missing) => missing.to_string(),
+                None => format!("field {}", headers.len() + 1),
+            };
+            let message = format!(
+                "row has {} fields, expected {}",
+                record.len(),
+                headers.len()
+            );
+            violations.push(Violation::new(line, &column, &message));
+        }
+        for ((name, rule), cell) in headers.iter().zip(&rules).zip(record.iter()) {
+            if let Some(rule) = rule {
+                if let Err(message) = check_cell(cell, rule) {
+                    violations.push(Violation::new(line, name, &message));
+                }
+            }
+        }
+    }
+    Ok(violations)
+}
+
+impl ColumnInference {
+    fn new() -> Self {
+        Self {
+            count: 0,
+            nulls: 0,
+            all_bool: true,
+            all_int: true,
+            all_number: true,
+            date_format: None,
+            distinct: Some(Vec::new()),
+            seen: HashSet::new(),
+        }
+    }
+
+    fn update(&mut self, cell: &str, max_enum: usize) {
+        self.count += 1;
+        if cell.is_empty() {
+            self.nulls += 1;
+            return;
+        }
+        let value = infer_value(cell);
+        self.all_bool &= value.is_boolean();
+        self.all_int &= value.is_i64();
+        self.all_number &= value.is_number();
+
+        let fmt = detect_date_format(cell);
+        self.date_format = match self.date_format {
+            None => Some(fmt),
+            Some(prev) if prev == fmt => Some(prev),
+            Some(_) => Some(None),
+        };
+
+        if let Some(distinct) = &mut self.distinct {
+            if self.seen.insert(cell.to_string()) {
+                if distinct.len() == max_enum {
+                    self.distinct = None;
+                    self.seen.clear();
+                } else {
+                    distinct.push(cell.to_string());
+                }
+            }
+        }
+    }
+
+    fn to_schema(&self) -> Value {
+        let non_null = self.count - self.nulls;
+        let ty = if non_null == 0 {
+            "string"
+        } else if self.all_bool {
+            "boolean"
+        } else if self.all_int {
+            "integer"
+        } else if self.all_number {
+            "number"
+        } else {
+            "string"
+        };
+        let mut schema = Map::new();
+        schema.insert(
+            "type".into(),
+            if self.nulls > 0 {
+                json!([ty, "null"])
+            } else {
+                json!(ty)
+            },
+        );
+        if ty == "string" {
+            match self.date_format {
+                Some(Some(ISO_DATE)) => {
+                    schema.insert("format".into(), json!("date"));
+                }
+                Some(Some(fmt)) => {
+                    schema.insert("x-date-format".into(), json!(fmt));
+                }
+                _ => {}
+            }
+        }
+        // 不同值很少并且有重复时，才认为是枚举
+        if let Some(distinct) = &self.distinct {
+            if !distinct.is_empty() && ty != "boolean" && non_null >= distinct.len() * 2 {
+                let values: Vec<Value> = distinct
+                    .iter()
+                    .map(|v| {
+                        if ty == "string" {
+                            json!(v)
+                        } else {
+                            infer_value(v)
+                        }
+                    })
+                    .collect();
+                schema.insert("enum".into(), Value::Array(values));
+            }
+        }
+        Value::Object(schema)
+    }
+}
+
+impl Violation {
+    fn new(line: u64, column: &str, message: &str) -> Self {
+        Self {
+            line,
+            column: column.to_string(),
+            message: message.to_string(),
+        }
+    }
+}
+
+fn check_cell(cell: &str, rule: &Value) -> Result<(), String> {
+    let types: Vec<&str> = match rule.get("type") {
+        Some(Value::String(t)) => vec![t.as_str()],
+        Some(Value::Array(ts)) => ts.iter().filter_map(|t| t.as_str()).collect(),
+        _ => vec![],
+    };
+    if cell.is_empty() {
+        return if types.is_empty() || types.contains(&"null") {
+            Ok(())
+        } else {
+            Err("value is required".to_string())
+        };
+    }
+
+    let typed = if types.is_empty() {
+        Value::String(cell.to_string())
+    } else {
+        types
+            .iter()
+            .find_map(|t| typed_value(cell, t))
+            .ok_or_else(|| format!("{:?} is not of type {}", cell, types.join(" or ")))?
+    };
+
+    if let Some(values) = rule.get("enum").and_then(|e| e.as_array()) {
+        if !values.contains(&typed) {
+            return Err(format!("{:?} is not one of the allowed values", cell));
+        }
+    }
+    if rule.get("format").and_then(|f| f.as_str()) == Some("date")
+        && parse_date_with(cell, ISO_DATE).is_none()
+    {
+        return Err(format!("{:?} is not a date", cell));
+    }
+    if let Some(fmt) = rule.get("x-date-format").and_then(|f| f.as_str()) {
+        if parse_date_with(cell, fmt).is_none() {
+            return Err(format!("{:?} does not match date format {}", cell, fmt));
+        }
+    }
+    if let Some(n) = typed.as_f64() {
+        if let Some(min) = rule.get("minimum").and_then(|m| m.as_f64()) {
+            if n < min {
+                return Err(format!("{} is less than the minimum {}", n, min));
+            }
+        }
+        if let Some(max) = rule.get("maximum").and_then(|m| m.as_f64()) {
+            if n > max {
+                return Err(format!("{} is greater than the maximum {}", n, max));
+            }
+        }
+    }
+    if let Value::String(s) = &typed {
+        let len = s.chars().count() as u64;
+        if let Some(min) = rule.get("minLength").and_then(|m| m.as_u64()) {
+            if len < min {
+                return Err(format!("{:?} is shorter than {} characters", s, min));
+            }
+        }
+        if let Some(max) = rule.get("maxLength").and_then(|m| m.as_u64()) {
+            if len > max {
+                return Err(format!("{:?} is longer than {} characters", s, max));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn typed_value(cell: &str, ty: &str) -> Option<Value> {
+    match ty {
+        "string" => Some(Value::String(cell.to_string())),
+        "integer" => cell.trim().parse::<i64>().ok().map(Value::from),
+        "number" => cell
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .filter(|f| f.is_finite())
+            .map(|f| {
+                infer_value(cell.trim())
+                    .as_i64()
+                    .map_or(Value::from(f), Value::from)
+            }),
+        "boolean" => parse_bool(cell.trim()).map(Value::Bool),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::{CsvDelimiter, CsvEncoding};
+
+    fn dialect() -> CsvDialectOpts {
+        CsvDialectOpts {
+            delimiter: CsvDelimiter::Char(b','),
+            header: None,
+            encoding: CsvEncoding::Auto,
+        }
+    }
+
+    #[test]
+    fn test_infer_juventus_schema() -> Result<()> {
+        let schema = process_csv_schema("assets/juventus.csv", &dialect(), 10)?;
+        let props = &schema["properties"];
+        assert_eq!(props["Kit Number"], json!({"type": "integer"}));
+        assert_eq!(props["DOB"]["x-date-format"], json!("%b %d, %Y"));
+        assert_eq!(props["Position"]["enum"].as_array().unwrap().len(), 10);
+        assert!(props["Name"].get("enum").is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_ragged_rows() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let input = dir.path().join("players.csv");
+        std::fs::write(
+            &input,
+            "Name,Kit Number\nDybala,10\nBuffon\nChiellini,3,Captain\nPjanic,ten\n",
+        )?;
+        let schema = dir.path().join("schema.yaml");
+        std::fs::write(
+            &schema,
+            "properties:\n  Name: {type: string}\n  Kit Number: {type: integer}\n",
+        )?;
+        let violations = process_csv_validate(
+            input.to_str().unwrap(),
+            &dialect(),
+            schema.to_str().unwrap(),
+        )?;
+        let found: Vec<(u64, &str)> = violations
+            .iter()
+            .map(|v| (v.line, v.column.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![(3, "Kit Number"), (4, "field 3"), (5, "Kit Number")]
+        );
+
+        // 没有header行时不检查列名，第一行也是数据
+        std::fs::write(&input, "Dybala,10\nPjanic,ten\n")?;
+        std::fs::write(
+            &schema,
+            "properties:\n  Name: {type: string}\n  Kit Number: {type: integer}\nrequired: [Name]\nadditionalProperties: false\n",
+        )?;
+        let no_header = CsvDialectOpts {
+            header: Some(false),
+            ..dialect()
+        };
+        let violations = process_csv_validate(
+            input.to_str().unwrap(),
+            &no_header,
+            schema.to_str().unwrap(),
+        )?;
+        let found: Vec<(u64, &str)> = violations
+            .iter()
+            .map(|v| (v.line, v.column.as_str()))
+            .collect();
+        assert_eq!(found, vec![(2, "Kit Number")]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_cell() {
+        let rule = json!({"type": ["integer", "null"], "minimum": 1, "maximum": 99});
+        assert!(check_cell("", &rule).is_ok());
+        assert!(check_cell("10", &rule).is_ok());
+        assert!(check_cell("100", &rule).is_err());
+        assert!(check_cell("ten", &rule).is_err());
+
+        let rule = json!({"type": "string", "enum": ["Goalkeeper", "Centre-Back"]});
+        assert!(check_cell("Goalkeeper", &rule).is_ok());
+        assert_eq!(
+            check_cell("Striker", &rule),
+            Err("\"Striker\" is not one of the allowed values".to_string())
+        );
+    }
+}
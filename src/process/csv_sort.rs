@@ -0,0 +1,294 @@
+use std::{
+    cmp::Ordering,
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Lines, Seek, Write},
+};
+
+use anyhow::Result;
+use serde_json::Value;
+
+use crate::cli::{CsvInputOpts, CsvOutputOpts, SortKey};
+
+use super::{
+    csv_convert::{open_records, RecordIter},
+    csv_query::{compare_values, is_null},
+    csv_writer::output_record_writer,
+};
+
+// 一次最多合并这么多块，块更多时分几轮合并
+const MAX_FAN_IN: usize = 64;
+
+pub fn process_csv_sort(
+    source: &CsvInputOpts,
+    keys: &[SortKey],
+    chunk_rows: usize,
+    target: &CsvOutputOpts,
+) -> Result<()> {
+    let (headers, records) = open_records(source)?;
+    for key in keys {
+        if !headers.contains(&key.column) {
+            anyhow::bail!("Unknown column: {}", key.column);
+        }
+    }
+
+    let config = target.writer_config(&source.dialect);
+    let mut writer =
+        output_record_writer(target.format, &headers, &config, target.output.as_deref())?;
+    for record in sort_records(records, keys, chunk_rows)? {
+        writer.write_record(&record?)?;
+    }
+    writer.finish()?;
+    Ok(())
+}
+
+/// stable sort of the records by the keys. at most `chunk_rows` records are held in memory,
+/// bigger inputs are sorted chunk by chunk into temporary files and then merged
+pub fn sort_records(
+    records: RecordIter,
+    keys: &[SortKey],
+    chunk_rows: usize,
+) -> Result<RecordIter> {
+    sort_with_fan_in(records, keys, chunk_rows, MAX_FAN_IN)
+}
+
+fn sort_with_fan_in(
+    records: RecordIter,
+    keys: &[SortKey],
+    chunk_rows: usize,
+    fan_in: usize,
+) -> Result<RecordIter> {
+    let chunk_rows = chunk_rows.max(1);
+    let fan_in = fan_in.max(2);
+    // levels[i]里的每个文件由fan_in^i块合并而来，越高的层越靠前
+    let mut levels: Vec<Vec<File>> = Vec::new();
+    let mut rows = Vec::new();
+    for record in records {
+        rows.push(record?);
+        if rows.len() == chunk_rows {
+            let chunk = spill(&mut rows, keys)?;
+            add_chunk(&mut levels, 0, chunk, keys, fan_in)?;
+        }
+    }
+    rows.sort_by(|a, b| compare_records(keys, a, b));
+    if levels.is_empty() {
+        return Ok(Box::new(rows.into_iter().map(Ok)));
+    }
+    if !rows.is_empty() {
+        let chunk = spill(&mut rows, keys)?;
+        add_chunk(&mut levels, 0, chunk, keys, fan_in)?;
+    }
+
+    let mut chunks: Vec<File> = levels.into_iter().rev().flatten().collect();
+    while chunks.len() > fan_in {
+        let mut merged = Vec::with_capacity(chunks.len().div_ceil(fan_in));
+        let mut rest = chunks.into_iter();
+        loop {
+            let group: Vec<File> = rest.by_ref().take(fan_in).collect();
+            if group.is_empty() {
+                break;
+            }
+            merged.push(merge_to_file(group, keys)?);
+        }
+        chunks = merged;
+    }
+    ChunkMerge::new(chunks, keys.to_vec()).map(|m| Box::new(m) as RecordIter)
+}
+
+/// compare two records by the keys, nulls sort first
+pub fn compare_records(keys: &[SortKey], a: &Value, b: &Value) -> Ordering {
+    for key in keys {
+        let a = a.get(&key.column).unwrap_or(&Value::Null);
+        let b = b.get(&key.column).unwrap_or(&Value::Null);
+        let ord = match (is_null(a), is_null(b)) {
+            (true, true) => Ordering::Equal,
+            (true, false) => Ordering::Less,
+            (false, true) => Ordering::Greater,
+            (false, false) => compare_values(a, b).unwrap_or(Ordering::Equal),
+        };
+        let ord = if key.desc { ord.reverse() } else { ord };
+        if ord != Ordering::Equal {
+            return ord;
+        }
+    }
+    Ordering::Equal
+}
+
+// 一层攒够fan_in块就合并成上一层的一块，同时打开的临时文件不超过fan_in乘以层数
+fn add_chunk(
+    levels: &mut Vec<Vec<File>>,
+    level: usize,
+    chunk: File,
+    keys: &[SortKey],
+    fan_in: usize,
+) -> Result<()> {
+    if levels.len() == level {
+        levels.push(Vec::new());
+    }
+    levels[level].push(chunk);
+    if levels[level].len() == fan_in {
+        let group = std::mem::take(&mut levels[level]);
+        let merged = merge_to_file(group, keys)?;
+        add_chunk(levels, level + 1, merged, keys, fan_in)?;
+    }
+    Ok(())
+}
+
+// 相邻的几块合并成一块，块的先后顺序不变，所以仍然是稳定的
+fn merge_to_file(chunks: Vec<File>, keys: &[SortKey]) -> Result<File> {
+    let mut writer = BufWriter::new(tempfile::tempfile()?);
+    for row in ChunkMerge::new(chunks, keys.to_vec())? {
+        serde_json::to_writer(&mut writer, &row?)?;
+        writer.write_all(b"\n")?;
+    }
+    let mut file = writer.into_inner()?;
+    file.rewind()?;
+    Ok(file)
+}
+
+// 排好序的一块写到临时文件，每行一个json；临时文件关闭后自动删除
+fn spill(rows: &mut Vec<Value>, keys: &[SortKey]) -> Result<File> {
+    rows.sort_by(|a, b| compare_records(keys, a, b));
+    let mut writer = BufWriter::new(tempfile::tempfile()?);
+    for row in rows.drain(..) {
+        serde_json::to_writer(&mut writer, &row)?;
+        writer.write_all(b"\n")?;
+    }
+    let mut file = writer.into_inner()?;
+    file.rewind()?;
+    Ok(file)
+}
+
+// k路归并：每次取所有块当前行中最小的一行，相等时取前面的块，保证稳定
+struct ChunkMerge {
+    keys: Vec<SortKey>,
+    readers: Vec<Lines<BufReader<File>>>,
+    heads: Vec<Option<Value>>,
+}
+
+impl ChunkMerge {
+    fn new(chunks: Vec<File>, keys: Vec<SortKey>) -> Result<Self> {
+        let mut readers: Vec<_> = chunks
+            .into_iter()
+            .map(|f| BufReader::new(f).lines())
+            .collect();
+        let heads = readers
+            .iter_mut()
+            .map(next_row)
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self {
+            keys,
+            readers,
+            heads,
+        })
+    }
+}
+
+impl Iterator for ChunkMerge {
+    type Item = Result<Value>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut min: Option<usize> = None;
+        for (i, head) in self.heads.iter().enumerate() {
+            let Some(head) = head else {
+                continue;
+            };
+            let smaller = match min.and_then(|m| self.heads[m].as_ref()) {
+                None => true,
+                Some(current) => compare_records(&self.keys, head, current) == Ordering::Less,
+            };
+            if smaller {
+                min = Some(i);
+            }
+        }
+        let i = min?;
+        let row = self.heads[i].take();
+        match next_row(&mut self.readers[i]) {
+            Ok(next) => self.heads[i] = next,
+            Err(e) => return Some(Err(e)),
+        }
+        row.map(Ok)
+    }
+}
+
+fn next_row(lines: &mut Lines<BufReader<File>>) -> Result<Option<Value>> {
+    match lines.next() {
+        Some(line) => Ok(Some(serde_json::from_str(&line?)?)),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_sort_spills_and_stays_stable() -> Result<()> {
+        let rows: Vec<Value> = (0..25)
+            .map(|i| json!({"group": (i * 7 % 5).to_string(), "seq": i}))
+            .collect();
+        let keys = vec!["group:desc".parse::<SortKey>()?];
+        let records: RecordIter = Box::new(rows.clone().into_iter().map(Ok));
+        let sorted = sort_records(records, &keys, 4)?.collect::<Result<Vec<_>>>()?;
+
+        let mut expected = rows;
+        expected.sort_by(|a, b| compare_records(&keys, a, b));
+        assert_eq!(sorted, expected);
+        assert_eq!(sorted[0], json!({"group": "4", "seq": 2}));
+        Ok(())
+    }
+
+    #[test]
+    fn test_sort_merges_in_passes() -> Result<()> {
+        let rows: Vec<Value> = (0..200)
+            .map(|i| json!({"group": (i * 13 % 7).to_string(), "seq": i}))
+            .collect();
+        let keys = vec!["group".parse::<SortKey>()?];
+        let mut expected = rows.clone();
+        expected.sort_by(|a, b| compare_records(&keys, a, b));
+        // 每行一块，200块远多于fan_in，会合并好几层
+        for fan_in in [2, 3, 64] {
+            let records: RecordIter = Box::new(rows.clone().into_iter().map(Ok));
+            let sorted =
+                sort_with_fan_in(records, &keys, 1, fan_in)?.collect::<Result<Vec<_>>>()?;
+            assert_eq!(sorted, expected);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_compare_records_numeric_and_nulls() {
+        let keys = vec!["Kit Number".parse::<SortKey>().unwrap()];
+        let a = json!({"Kit Number": "9"});
+        let b = json!({"Kit Number": "10"});
+        let empty = json!({"Kit Number": ""});
+        assert_eq!(compare_records(&keys, &a, &b), Ordering::Less);
+        assert_eq!(compare_records(&keys, &empty, &a), Ordering::Less);
+    }
+
+    #[test]
+    fn test_compare_records_mixed_column() -> Result<()> {
+        let keys = vec!["id".parse::<SortKey>()?];
+        let rows: Vec<Value> = ["1a", "10", "b", "2", "1.5", "10"]
+            .iter()
+            .map(|id| json!({ "id": id }))
+            .collect();
+        // 任意三个值都满足传递性
+        for a in &rows {
+            for b in &rows {
+                for c in &rows {
+                    if compare_records(&keys, a, b).is_le() && compare_records(&keys, b, c).is_le()
+                    {
+                        assert!(compare_records(&keys, a, c).is_le());
+                    }
+                }
+            }
+        }
+        let records: RecordIter = Box::new(rows.into_iter().map(Ok));
+        let ids: Vec<Value> = sort_records(records, &keys, 2)?
+            .map(|r| r.map(|r| r["id"].clone()))
+            .collect::<Result<_>>()?;
+        assert_eq!(ids, vec!["1.5", "2", "10", "10", "1a", "b"]);
+        Ok(())
+    }
+}
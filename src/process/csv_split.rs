@@ -0,0 +1,406 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde_json::{Map, Value};
+
+use crate::cli::{CsvInputOpts, CsvOutputOpts, OutputFormat, SplitBy};
+
+use super::{
+    compression::strip_compression,
+    csv_convert::{open_records, value_to_cell, RecordIter},
+    csv_writer::{output_record_writer, RecordWriter, WriterConfig},
+};
+
+// 按列拆分时最多同时打开这么多个临时文件，远低于常见的文件描述符上限
+const MAX_OPEN_PARTS: usize = 32;
+
+/// write the input into numbered parts, or one part per value of a column, each part with
+/// the full header. the path of every part is printed as it is created
+pub fn process_csv_split(
+    source: &CsvInputOpts,
+    by: &SplitBy,
+    out_dir: &Path,
+    prefix: Option<&str>,
+    format: OutputFormat,
+) -> Result<()> {
+    let (headers, records) = open_records(source)?;
+    let prefix = match prefix {
+        Some(prefix) => prefix.to_string(),
+        // players.csv.gz的前缀是players，不是players.csv
+        None => Path::new(strip_compression(&source.input))
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .filter(|_| source.input != "-")
+            .unwrap_or("part")
+            .to_string(),
+    };
+    let config = WriterConfig {
+        delimiter: source.dialect.output_delimiter(),
+        ..Default::default()
+    };
+    let parts = Parts {
+        out_dir,
+        prefix,
+        format,
+        headers: &headers,
+        config,
+    };
+
+    match by {
+        SplitBy::Column(column) => {
+            if !headers.contains(column) {
+                anyhow::bail!("Unknown column: {}", column);
+            }
+            split_by_column(&parts, column, records, MAX_OPEN_PARTS)?;
+        }
+        SplitBy::Rows(_) | SplitBy::Bytes(_) => {
+            let mut writer: Option<Box<dyn RecordWriter>> = None;
+            let (mut part, mut rows, mut size) = (0, 0, 0);
+            for record in records {
+                let record = record?;
+                let record_size = csv_size(&headers, &record, parts.config.delimiter);
+                let full = match by {
+                    SplitBy::Rows(n) => rows >= *n,
+                    SplitBy::Bytes(n) => size + record_size > *n,
+                    SplitBy::Column(_) => false,
+                };
+                if writer.is_none() || (full && rows > 0) {
+                    if let Some(mut w) = writer.take() {
+                        w.finish()?;
+                    }
+                    part += 1;
+                    writer = Some(parts.open(&format!("{:04}", part))?);
+                    rows = 0;
+                    size = csv_header_size(&headers, parts.config.delimiter);
+                }
+                if let Some(w) = writer.as_mut() {
+                    w.write_record(&record)?;
+                }
+                rows += 1;
+                size += record_size;
+            }
+            match writer {
+                Some(mut w) => w.finish()?,
+                // 空文件也输出一个只有表头的部分
+                None => parts.open("0001")?.finish()?,
+            }
+        }
+    }
+    Ok(())
+}
+
+// 先按值追加到临时的ndjson文件里，只保留最近用过的max_open个打开着，
+// 读完后再逐个转成最终格式，任何时候只多打开一个输出文件
+fn split_by_column(
+    parts: &Parts,
+    column: &str,
+    records: RecordIter,
+    max_open: usize,
+) -> Result<()> {
+    let spool_dir = tempfile::Builder::new()
+        .prefix(".rcli-split-")
+        .tempdir_in(parts.out_dir)?;
+    let mut values: HashMap<String, usize> = HashMap::new();
+    let mut names = HashSet::new();
+    let mut spools: Vec<(String, PathBuf)> = Vec::new();
+    // 下标 -> (打开的文件, 最后一次使用的序号)
+    let mut open: HashMap<usize, (BufWriter<File>, usize)> = HashMap::new();
+
+    for (i, record) in records.enumerate() {
+        let record = record?;
+        let value = record.get(column).map(value_to_cell).unwrap_or_default();
+        let idx = match values.get(&value) {
+            Some(idx) => *idx,
+            None => {
+                let name = unique_name(&mut names, file_name_part(&value));
+                let path = spool_dir.path().join(format!("{}.ndjson", spools.len()));
+                spools.push((name, path));
+                values.insert(value, spools.len() - 1);
+                spools.len() - 1
+            }
+        };
+        if !open.contains_key(&idx) {
+            if open.len() >= max_open.max(1) {
+                let oldest = open
+                    .iter()
+                    .min_by_key(|(_, (_, used))| *used)
+                    .map(|(idx, _)| *idx)
+                    .expect("open won't be empty in this context");
+                if let Some((mut w, _)) = open.remove(&oldest) {
+                    w.flush()?;
+                }
+            }
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&spools[idx].1)?;
+            open.insert(idx, (BufWriter::new(file), i));
+        }
+        if let Some((w, used)) = open.get_mut(&idx) {
+            serde_json::to_writer(&mut *w, &record)?;
+            w.write_all(b"\n")?;
+            *used = i;
+        }
+    }
+    for (_, (mut w, _)) in open.drain() {
+        w.flush()?;
+    }
+
+    for (name, path) in &spools {
+        let mut writer = parts.open(name)?;
+        for line in BufReader::new(File::open(path)?).lines() {
+            writer.write_record(&serde_json::from_str(&line?)?)?;
+        }
+        writer.finish()?;
+    }
+    Ok(())
+}
+
+/// concatenate the inputs. the columns are the union of all headers in the order they first
+/// appear, cells a file doesn't have are null
+pub fn process_csv_cat(sources: &[CsvInputOpts], target: &CsvOutputOpts) -> Result<()> {
+    let mut columns: Vec<String> = Vec::new();
+    let mut inputs = Vec::with_capacity(sources.len());
+    for source in sources {
+        let (headers, records) = open_records(source)?;
+        for h in headers {
+            if !columns.contains(&h) {
+                columns.push(h);
+            }
+        }
+        inputs.push(records);
+    }
+
+    let dialect = &sources
+        .first()
+        .ok_or_else(|| anyhow::anyhow!("No input files"))?
+        .dialect;
+    let config = target.writer_config(dialect);
+    let mut writer =
+        output_record_writer(target.format, &columns, &config, target.output.as_deref())?;
+    for record in inputs.into_iter().flatten() {
+        let record = record?;
+        let aligned: Map<String, Value> = columns
+            .iter()
+            .map(|c| (c.clone(), record.get(c).cloned().unwrap_or(Value::Null)))
+            .collect();
+        writer.write_record(&Value::Object(aligned))?;
+    }
+    writer.finish()?;
+    Ok(())
+}
+
+/// pick `size` random rows in one pass and write them in their original order
+pub fn process_csv_sample(
+    source: &CsvInputOpts,
+    size: usize,
+    seed: Option<u64>,
+    target: &CsvOutputOpts,
+) -> Result<()> {
+    let (headers, records) = open_records(source)?;
+    let mut rng = match seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+    let rows = sample_records(records, size, &mut rng)?;
+
+    let config = target.writer_config(&source.dialect);
+    let mut writer =
+        output_record_writer(target.format, &headers, &config, target.output.as_deref())?;
+    for row in &rows {
+        writer.write_record(row)?;
+    }
+    writer.finish()?;
+    Ok(())
+}
+
+/// reservoir sampling (algorithm R): every row ends up in the sample with the same probability
+pub fn sample_records(records: RecordIter, size: usize, rng: &mut impl Rng) -> Result<Vec<Value>> {
+    let mut reservoir: Vec<(usize, Value)> = Vec::with_capacity(size);
+    for (i, record) in records.enumerate() {
+        let record = record?;
+        if reservoir.len() < size {
+            reservoir.push((i, record));
+        } else {
+            let j = rng.gen_range(0..=i);
+            if j < size {
+                reservoir[j] = (i, record);
+            }
+        }
+    }
+    reservoir.sort_by_key(|(i, _)| *i);
+    Ok(reservoir.into_iter().map(|(_, r)| r).collect())
+}
+
+struct Parts<'a> {
+    out_dir: &'a Path,
+    prefix: String,
+    format: OutputFormat,
+    headers: &'a [String],
+    config: WriterConfig,
+}
+
+impl Parts<'_> {
+    fn open(&self, name: &str) -> Result<Box<dyn RecordWriter>> {
+        let file = format!("{}_{}.{}", self.prefix, name, self.format);
+        let path: PathBuf = self.out_dir.join(file);
+        let path = path
+            .to_str()
+            .ok_or_else(|| anyhow::anyhow!("Invalid path: {}", path.display()))?;
+        let writer = output_record_writer(self.format, self.headers, &self.config, Some(path))?;
+        println!("{}", path);
+        Ok(writer)
+    }
+}
+
+// 按csv估算大小：需要时加引号，引号转义成两个，每行一个换行
+fn csv_size(headers: &[String], record: &Value, delimiter: u8) -> u64 {
+    let cells = headers
+        .iter()
+        .map(|h| record.get(h).map(value_to_cell).unwrap_or_default());
+    line_size(cells, delimiter)
+}
+
+fn csv_header_size(headers: &[String], delimiter: u8) -> u64 {
+    line_size(headers.iter().cloned(), delimiter)
+}
+
+fn line_size(cells: impl Iterator<Item = String>, delimiter: u8) -> u64 {
+    let mut size = 0;
+    let mut count = 0;
+    for cell in cells {
+        count += 1;
+        size += cell.len() as u64;
+        if cell
+            .bytes()
+            .any(|b| b == delimiter || matches!(b, b'"' | b'\n' | b'\r'))
+        {
+            size += 2 + cell.bytes().filter(|b| *b == b'"').count() as u64;
+        }
+    }
+    size + count.max(1) as u64
+}
+
+// 值里文件名不能用的字符换成_
+fn file_name_part(value: &str) -> String {
+    let name: String = value
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || matches!(c, '-' | '_') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if name.is_empty() {
+        "empty".to_string()
+    } else {
+        name
+    }
+}
+
+// 不同的值换成文件名后可能相同，加上序号区分
+fn unique_name(names: &mut HashSet<String>, name: String) -> String {
+    let mut candidate = name.clone();
+    let mut n = 1;
+    while !names.insert(candidate.clone()) {
+        n += 1;
+        candidate = format!("{}_{}", name, n);
+    }
+    candidate
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_sample_records() -> Result<()> {
+        let rows = || -> RecordIter { Box::new((0..100).map(|i| Ok(json!({ "id": i })))) };
+        let a = sample_records(rows(), 10, &mut StdRng::seed_from_u64(42))?;
+        let b = sample_records(rows(), 10, &mut StdRng::seed_from_u64(42))?;
+        assert_eq!(a.len(), 10);
+        assert_eq!(a, b);
+        // 按原来的顺序输出
+        let ids: Vec<i64> = a.iter().map(|r| r["id"].as_i64().unwrap()).collect();
+        assert!(ids.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(
+            sample_records(rows(), 200, &mut StdRng::seed_from_u64(1))?.len(),
+            100
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_by_column() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let source = CsvInputOpts {
+            input: "assets/juventus.csv".to_string(),
+            input_format: None,
+            dialect: crate::cli::CsvDialectOpts {
+                delimiter: ",".parse()?,
+                header: None,
+                encoding: "auto".parse()?,
+            },
+            infer: false,
+            types: vec![],
+        };
+        let by = SplitBy::Column("Position".to_string());
+        process_csv_split(&source, &by, dir.path(), None, OutputFormat::Csv)?;
+        let keepers = std::fs::read_to_string(dir.path().join("juventus_Goalkeeper.csv"))?;
+        assert!(keepers.starts_with("Name,Position,DOB,Nationality,Kit Number\n"));
+        assert_eq!(keepers.lines().count(), 5);
+
+        // 压缩文件的默认前缀不带.csv
+        let gz = dir.path().join("juventus.csv.gz");
+        let mut encoder =
+            flate2::write::GzEncoder::new(File::create(&gz)?, flate2::Compression::default());
+        encoder.write_all(&std::fs::read("assets/juventus.csv")?)?;
+        encoder.finish()?;
+        let parts = tempfile::tempdir()?;
+        let source = CsvInputOpts {
+            input: gz.to_str().unwrap().to_string(),
+            ..source
+        };
+        process_csv_split(&source, &by, parts.path(), None, OutputFormat::Csv)?;
+        assert!(parts.path().join("juventus_Goalkeeper.csv").exists());
+
+        // 每个球员一个文件，同时只开两个临时文件
+        let names = tempfile::tempdir()?;
+        let parts = Parts {
+            out_dir: names.path(),
+            prefix: "player".to_string(),
+            format: OutputFormat::Json,
+            headers: &["Name".to_string(), "Kit Number".to_string()],
+            config: WriterConfig::default(),
+        };
+        let records: RecordIter = Box::new(
+            ["Dybala", "Buffon", "Dybala", "Chiellini", "Buffon"]
+                .into_iter()
+                .enumerate()
+                .map(|(i, name)| Ok(json!({ "Name": name, "Kit Number": i }))),
+        );
+        split_by_column(&parts, "Name", records, 2)?;
+        let dybala: Value = serde_json::from_str(&std::fs::read_to_string(
+            names.path().join("player_Dybala.json"),
+        )?)?;
+        assert_eq!(
+            dybala,
+            json!([{ "Name": "Dybala", "Kit Number": 0 }, { "Name": "Dybala", "Kit Number": 2 }])
+        );
+        assert_eq!(std::fs::read_dir(names.path())?.count(), 3);
+
+        assert_eq!(file_name_part("Centre-Back"), "Centre-Back");
+        assert_eq!(file_name_part("a/b c"), "a_b_c");
+        assert_eq!(file_name_part(""), "empty");
+        Ok(())
+    }
+}
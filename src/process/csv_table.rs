@@ -0,0 +1,263 @@
+use std::{
+    collections::VecDeque,
+    io::{self, IsTerminal, Write},
+};
+
+use anyhow::Result;
+use serde_json::Value;
+use unicode_width::UnicodeWidthChar;
+
+use crate::cli::CsvOpts;
+
+use super::csv_convert::{converted_records, value_to_cell};
+
+// stdout不是终端时（比如接了less），每攒够这么多行就输出一个表格，不必等整个文件读完
+const PAGE_ROWS: usize = 1000;
+
+/// render rows as a unicode box-drawing table
+#[derive(Debug, Clone)]
+pub struct TableRenderer {
+    /// cells wider than this are truncated with "…"
+    pub max_col_width: usize,
+    /// shrink columns so the whole table fits in this width
+    pub max_table_width: Option<usize>,
+}
+
+impl TableRenderer {
+    pub fn render(&self, headers: &[String], rows: &[Vec<Cell>]) -> String {
+        let header_cells: Vec<Cell> = headers.iter().map(|h| Cell::text(h)).collect();
+        let mut widths: Vec<usize> = header_cells
+            .iter()
+            .map(|h| display_width(&h.text))
+            .collect();
+        for row in rows {
+            for (w, cell) in widths.iter_mut().zip(row) {
+                *w = (*w).max(display_width(&cell.text));
+            }
+        }
+        for w in widths.iter_mut() {
+            *w = (*w).clamp(1, self.max_col_width.max(1));
+        }
+        if let Some(max) = self.max_table_width {
+            fit_widths(&mut widths, max);
+        }
+
+        let mut out = String::new();
+        out.push_str(&border(&widths, '┌', '┬', '┐'));
+        out.push_str(&line(&widths, &header_cells));
+        out.push_str(&border(&widths, '├', '┼', '┤'));
+        for row in rows {
+            out.push_str(&line(&widths, row));
+        }
+        out.push_str(&border(&widths, '└', '┴', '┘'));
+        out
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Cell {
+    text: String,
+    right_align: bool,
+}
+
+impl Cell {
+    /// control characters (newlines, tabs, ...) become spaces so they can't break the table
+    pub fn text(s: &str) -> Self {
+        Self {
+            text: s
+                .chars()
+                .map(|c| if c.is_control() { ' ' } else { c })
+                .collect(),
+            right_align: false,
+        }
+    }
+
+    /// numbers are right aligned, everything else left aligned
+    pub fn from_value(value: &Value) -> Self {
+        let mut cell = Self::text(&value_to_cell(value));
+        cell.right_align = value.is_number();
+        cell
+    }
+}
+
+pub fn process_csv_show(opts: &CsvOpts) -> Result<()> {
+    let (headers, records) = converted_records(opts)?;
+    let headers = if opts.columns.is_empty() {
+        headers
+    } else {
+        opts.columns.clone()
+    };
+    let to_row = |record: &Value| -> Vec<Cell> {
+        headers
+            .iter()
+            .map(|h| {
+                record
+                    .get(h)
+                    .map(Cell::from_value)
+                    .unwrap_or(Cell::text(""))
+            })
+            .collect()
+    };
+
+    let is_tty = io::stdout().is_terminal();
+    let renderer = TableRenderer {
+        max_col_width: opts.max_width,
+        max_table_width: if is_tty {
+            terminal_size::terminal_size().map(|(w, _)| w.0 as usize)
+        } else {
+            None
+        },
+    };
+    let mut stdout = io::stdout().lock();
+
+    if let Some(n) = opts.tail {
+        let mut rows = VecDeque::with_capacity(n);
+        for record in records {
+            if rows.len() == n {
+                rows.pop_front();
+            }
+            if n > 0 {
+                rows.push_back(to_row(&record?));
+            }
+        }
+        let rows: Vec<_> = rows.into_iter().collect();
+        write!(stdout, "{}", renderer.render(&headers, &rows))?;
+        return Ok(());
+    }
+
+    let records = records.take(opts.head.unwrap_or(usize::MAX));
+    if is_tty {
+        let rows = records
+            .map(|r| r.map(|r| to_row(&r)))
+            .collect::<Result<Vec<_>>>()?;
+        write!(stdout, "{}", renderer.render(&headers, &rows))?;
+    } else {
+        let mut rows = Vec::with_capacity(PAGE_ROWS);
+        let mut printed = false;
+        for record in records {
+            rows.push(to_row(&record?));
+            if rows.len() == PAGE_ROWS {
+                write!(stdout, "{}", renderer.render(&headers, &rows))?;
+                rows.clear();
+                printed = true;
+            }
+        }
+        if !rows.is_empty() || !printed {
+            write!(stdout, "{}", renderer.render(&headers, &rows))?;
+        }
+    }
+    Ok(())
+}
+
+// 表格太宽时，每次把最宽的一列缩小一格，直到放得下
+fn fit_widths(widths: &mut [usize], max: usize) {
+    // 每列两侧各一个空格加一个竖线，最后再加一个竖线
+    let overhead = widths.len() * 3 + 1;
+    while widths.iter().sum::<usize>() + overhead > max {
+        let Some(widest) = widths.iter_mut().filter(|w| **w > 3).max_by_key(|w| **w) else {
+            break;
+        };
+        *widest -= 1;
+    }
+}
+
+fn border(widths: &[usize], left: char, mid: char, right: char) -> String {
+    let parts: Vec<String> = widths.iter().map(|w| "─".repeat(w + 2)).collect();
+    format!("{}{}{}\n", left, parts.join(&mid.to_string()), right)
+}
+
+fn line(widths: &[usize], cells: &[Cell]) -> String {
+    let mut out = String::from("│");
+    for (i, w) in widths.iter().enumerate() {
+        let (text, right_align) = match cells.get(i) {
+            Some(cell) => (truncate(&cell.text, *w), cell.right_align),
+            None => (String::new(), false),
+        };
+        let pad = " ".repeat(w.saturating_sub(display_width(&text)));
+        if right_align {
+            out.push_str(&format!(" {}{} │", pad, text));
+        } else {
+            out.push_str(&format!(" {}{} │", text, pad));
+        }
+    }
+    out.push('\n');
+    out
+}
+
+// 截断和补齐都用这一个宽度，两边算法不一致会让补齐的空格算错
+fn display_width(s: &str) -> usize {
+    s.chars().map(char_width).sum()
+}
+
+fn char_width(c: char) -> usize {
+    c.width().unwrap_or(0)
+}
+
+// 按显示宽度截断，中文等全角字符占两格
+fn truncate(s: &str, width: usize) -> String {
+    if display_width(s) <= width {
+        return s.to_string();
+    }
+    let mut ret = String::new();
+    let mut used = 0;
+    for c in s.chars() {
+        let w = char_width(c);
+        if used + w + 1 > width {
+            break;
+        }
+        ret.push(c);
+        used += w;
+    }
+    ret.push('…');
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_render_table() {
+        let renderer = TableRenderer {
+            max_col_width: 6,
+            max_table_width: None,
+        };
+        let headers = vec!["name".to_string(), "kit".to_string()];
+        let rows = vec![
+            vec![
+                Cell::from_value(&json!("Chiellini")),
+                Cell::from_value(&json!(3)),
+            ],
+            vec![
+                Cell::from_value(&json!("基耶利尼")),
+                Cell::from_value(&json!(10)),
+            ],
+        ];
+        let expected = "\
+┌────────┬─────┐
+│ name   │ kit │
+├────────┼─────┤
+│ Chiel… │   3 │
+│ 基耶…  │  10 │
+└────────┴─────┘
+";
+        assert_eq!(renderer.render(&headers, &rows), expected);
+    }
+
+    #[test]
+    fn test_render_control_chars() {
+        let renderer = TableRenderer {
+            max_col_width: 5,
+            max_table_width: None,
+        };
+        let headers = vec!["name".to_string()];
+        let rows = vec![
+            vec![Cell::from_value(&json!("\t\t\t\t\t\t\tDybala"))],
+            vec![Cell::from_value(&json!("\x01\x01\x01\x01\x01\x01"))],
+        ];
+        let table = renderer.render(&headers, &rows);
+        assert!(table.lines().all(|l| display_width(l) == 9));
+        assert!(!table.contains(['\t', '\x01']));
+    }
+}
@@ -0,0 +1,191 @@
+use anyhow::Result;
+use chrono::NaiveDate;
+use csv::{ByteRecord, StringRecord};
+use serde_json::{Map, Number, Value};
+
+use crate::cli::{ColumnType, ColumnTypeOverride};
+
+use super::csv_errors::{to_string_record, RowError};
+
+const DATE_FORMATS: &[&str] = &["%Y-%m-%d", "%Y/%m/%d", "%b %d, %Y", "%B %d, %Y", "%d %b %Y"];
+
+/// turn CSV records into JSON values, optionally inferring or forcing the type of each cell
+#[derive(Debug, Clone)]
+pub struct ValueConverter {
+    infer: bool,
+    // 按列的位置存放，避免每个cell都按名字查找
+    types: Vec<Option<ColumnType>>,
+}
+
+impl ValueConverter {
+    pub fn new(
+        headers: &StringRecord,
+        infer: bool,
+        overrides: &[ColumnTypeOverride],
+    ) -> Result<Self> {
+        let mut types = vec![None; headers.len()];
+        for o in overrides {
+            let idx = headers
+                .iter()
+                .position(|h| h == o.column)
+                .ok_or_else(|| anyhow::anyhow!("Unknown column in --type: {}", o.column))?;
+            types[idx] = Some(o.ty);
+        }
+        Ok(Self { infer, types })
+    }
+
+    /// check and convert a raw record, every problem with the row is returned as a `RowError`
+    pub fn convert_raw(&self, headers: &StringRecord, record: ByteRecord) -> Result<Value> {
+        let record = to_string_record(headers, record)?;
+        self.convert(headers, &record)
+            .map_err(|e| RowError::new(record.as_byte_record(), e.to_string()).into())
+    }
+
+    pub fn convert(&self, headers: &StringRecord, record: &StringRecord) -> Result<Value> {
+        let mut map = Map::with_capacity(headers.len());
+        for (i, (header, cell)) in headers.iter().zip(record.iter()).enumerate() {
+            let value = match self.types.get(i).copied().flatten() {
+                Some(ty) => parse_typed(cell, ty).map_err(|e| {
+                    let line = record.position().map(|p| p.line()).unwrap_or_default();
+                    anyhow::anyhow!("row {}, column {} ({}): {}", line, i + 1, header, e)
+                })?,
+                None if self.infer => infer_value(cell),
+                None => Value::String(cell.to_string()),
+            };
+            map.insert(header.to_string(), value);
+        }
+        Ok(Value::Object(map))
+    }
+}
+
+/// only convert a cell when its type is unambiguous, e.g. "007" stays a string
+pub fn infer_value(cell: &str) -> Value {
+    if cell.is_empty() {
+        return Value::Null;
+    }
+    if let Some(b) = parse_bool(cell) {
+        return Value::Bool(b);
+    }
+    if is_plain_number(cell) {
+        if let Ok(i) = cell.parse::<i64>() {
+            return Value::Number(i.into());
+        }
+        if cell.contains(['.', 'e', 'E']) {
+            if let Some(n) = cell.parse::<f64>().ok().and_then(Number::from_f64) {
+                return Value::Number(n);
+            }
+        }
+    }
+    Value::String(cell.to_string())
+}
+
+pub fn parse_typed(cell: &str, ty: ColumnType) -> Result<Value> {
+    if cell.is_empty() && ty != ColumnType::String {
+        return Ok(Value::Null);
+    }
+    let value = match ty {
+        ColumnType::String => Value::String(cell.to_string()),
+        ColumnType::Int => cell
+            .trim()
+            .parse::<i64>()
+            .map(|i| Value::Number(i.into()))
+            .map_err(|_| anyhow::anyhow!("cannot parse {:?} as int", cell))?,
+        ColumnType::Float => cell
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .and_then(Number::from_f64)
+            .map(Value::Number)
+            .ok_or_else(|| anyhow::anyhow!("cannot parse {:?} as float", cell))?,
+        ColumnType::Bool => parse_bool(cell.trim())
+            .map(Value::Bool)
+            .ok_or_else(|| anyhow::anyhow!("cannot parse {:?} as bool", cell))?,
+        ColumnType::Date => parse_date(cell)
+            .map(|d| Value::String(d.format("%Y-%m-%d").to_string()))
+            .ok_or_else(|| anyhow::anyhow!("cannot parse {:?} as date", cell))?,
+    };
+    Ok(value)
+}
+
+/// parse a date in one of the common formats, a trailing note in parentheses is allowed,
+/// e.g. "Apr 18, 1990 (29)" in juventus.csv
+pub fn parse_date(cell: &str) -> Option<NaiveDate> {
+    DATE_FORMATS
+        .iter()
+        .find_map(|fmt| parse_date_with(cell, fmt))
+}
+
+/// the first of the common date formats that `cell` matches, as a chrono format string
+pub fn detect_date_format(cell: &str) -> Option<&'static str> {
+    DATE_FORMATS
+        .iter()
+        .find(|fmt| parse_date_with(cell, fmt).is_some())
+        .copied()
+}
+
+pub fn parse_date_with(cell: &str, fmt: &str) -> Option<NaiveDate> {
+    let (date, rest) = NaiveDate::parse_and_remainder(cell.trim(), fmt).ok()?;
+    let rest = rest.trim();
+    (rest.is_empty() || (rest.starts_with('(') && rest.ends_with(')'))).then_some(date)
+}
+
+pub fn parse_bool(cell: &str) -> Option<bool> {
+    match cell {
+        "true" | "True" | "TRUE" => Some(true),
+        "false" | "False" | "FALSE" => Some(false),
+        _ => None,
+    }
+}
+
+// 不接受前导0、前导+号、NaN/inf这类写法
+fn is_plain_number(cell: &str) -> bool {
+    let digits = cell.strip_prefix('-').unwrap_or(cell);
+    let int_part = digits.split(['.', 'e', 'E']).next().unwrap_or_default();
+    !int_part.is_empty()
+        && int_part.bytes().all(|b| b.is_ascii_digit())
+        && (int_part == "0" || !int_part.starts_with('0'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_infer_value() {
+        assert_eq!(infer_value("10"), json!(10));
+        assert_eq!(infer_value("-1.5"), json!(-1.5));
+        assert_eq!(infer_value("true"), json!(true));
+        assert_eq!(infer_value(""), Value::Null);
+        assert_eq!(infer_value("007"), json!("007"));
+        assert_eq!(infer_value("NaN"), json!("NaN"));
+        assert_eq!(infer_value("1,000"), json!("1,000"));
+    }
+
+    #[test]
+    fn test_type_override_error_position() -> Result<()> {
+        let data =
+            "Name,Kit Number,DOB\nPaulo Dybala,10,\"Nov 15, 1993 (25)\"\nBad,ten,\"Jan 1, 2000\"\n";
+        let mut reader = csv::Reader::from_reader(data.as_bytes());
+        let headers = reader.headers()?.clone();
+        let overrides: Vec<ColumnTypeOverride> =
+            vec!["Kit Number=int".parse()?, "DOB=date".parse()?];
+        let converter = ValueConverter::new(&headers, false, &overrides)?;
+        let mut records = reader.records();
+
+        let first = converter.convert(&headers, &records.next().unwrap()?)?;
+        assert_eq!(
+            first,
+            json!({"Name": "Paulo Dybala", "Kit Number": 10, "DOB": "1993-11-15"})
+        );
+
+        let err = converter
+            .convert(&headers, &records.next().unwrap()?)
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "row 3, column 2 (Kit Number): cannot parse \"ten\" as int"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,702 @@
+use std::{fs::File, io::Write};
+
+use anyhow::Result;
+use csv::WriterBuilder;
+use serde_json::Value;
+
+use crate::cli::OutputFormat;
+
+use super::{
+    compression::{create_output, Compression, OutputFile},
+    csv_binary::binary_record_writer,
+    csv_convert::value_to_cell,
+};
+
+/// write converted records one at a time, so the whole file never has to be in memory
+pub trait RecordWriter {
+    fn write_record(&mut self, record: &Value) -> Result<()>;
+    /// write whatever closes the document and flush the underlying writer
+    fn finish(&mut self) -> Result<()>;
+    /// write `records` records serialized by `serialize_records`
+    fn write_chunk(&mut self, _chunk: &[u8], _records: usize) -> Result<()> {
+        anyhow::bail!("this output format can't be written in chunks")
+    }
+}
+
+pub struct JsonArrayWriter<W: Write> {
+    writer: W,
+    count: usize,
+}
+
+pub struct NdjsonWriter<W: Write> {
+    writer: W,
+}
+
+pub struct YamlWriter<W: Write> {
+    writer: W,
+    count: usize,
+}
+
+pub struct CsvRecordWriter<W: Write> {
+    // 只有write_chunk会临时把它拆开，其余时候一定是Some
+    writer: Option<csv::Writer<W>>,
+    columns: Vec<String>,
+    delimiter: u8,
+}
+
+pub struct TomlWriter<W: Write> {
+    writer: W,
+    table_name: String,
+    count: usize,
+}
+
+pub struct XmlWriter<W: Write> {
+    writer: W,
+    root: String,
+    row: String,
+}
+
+pub struct MarkdownWriter<W: Write> {
+    writer: W,
+    columns: Vec<String>,
+}
+
+pub struct HtmlWriter<W: Write> {
+    writer: W,
+    columns: Vec<String>,
+}
+
+/// settings some of the output formats need besides the column list
+#[derive(Debug, Clone)]
+pub struct WriterConfig {
+    /// delimiter of csv output
+    pub delimiter: u8,
+    /// root element of xml output
+    pub root_name: String,
+    /// row element of xml output, and the array of tables name of toml output
+    pub row_name: String,
+}
+
+impl Default for WriterConfig {
+    fn default() -> Self {
+        Self {
+            delimiter: b',',
+            root_name: "rows".to_string(),
+            row_name: "row".to_string(),
+        }
+    }
+}
+
+/// the output file, or stdout when no file is given or it is "-".
+/// .gz, .zst and .bz2 files are compressed
+pub fn output_writer(output: Option<&str>) -> Result<OutputFile> {
+    Ok(match output {
+        Some(output) => create_output(output)?,
+        None => OutputFile::stdout(),
+    })
+}
+
+// 记录写完后还要结束输出文件，压缩流的结尾写失败时要报错
+struct FinishOutput {
+    inner: Box<dyn RecordWriter>,
+    output: OutputFile,
+}
+
+impl RecordWriter for FinishOutput {
+    fn write_record(&mut self, record: &Value) -> Result<()> {
+        self.inner.write_record(record)
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.inner.finish()?;
+        self.output.finish()?;
+        Ok(())
+    }
+
+    fn write_chunk(&mut self, chunk: &[u8], records: usize) -> Result<()> {
+        self.inner.write_chunk(chunk, records)
+    }
+}
+
+/// record writer on the output file, or on stdout when no file is given.
+/// the binary formats can only be written to a file
+pub fn output_record_writer(
+    format: OutputFormat,
+    columns: &[String],
+    config: &WriterConfig,
+    output: Option<&str>,
+) -> Result<Box<dyn RecordWriter>> {
+    match output {
+        Some(path) if format.is_binary() && path != "-" => {
+            if Compression::from_path(path).is_some() {
+                anyhow::bail!(
+                    "{} is a binary format and can't be compressed: {}",
+                    format,
+                    path
+                );
+            }
+            binary_record_writer(format, columns, File::create(path)?)
+        }
+        output => {
+            let output = output_writer(output)?;
+            let inner = record_writer(format, columns, config, output.clone())?;
+            Ok(Box::new(FinishOutput { inner, output }))
+        }
+    }
+}
+
+pub fn record_writer<'a>(
+    format: OutputFormat,
+    columns: &[String],
+    config: &WriterConfig,
+    writer: impl Write + 'a,
+) -> Result<Box<dyn RecordWriter + 'a>> {
+    let writer: Box<dyn RecordWriter> = match format {
+        OutputFormat::Json => Box::new(JsonArrayWriter::new(writer)),
+        OutputFormat::Ndjson => Box::new(NdjsonWriter::new(writer)),
+        OutputFormat::Yaml => Box::new(YamlWriter::new(writer)),
+        OutputFormat::Csv => Box::new(CsvRecordWriter::try_new(writer, columns, config.delimiter)?),
+        OutputFormat::Toml => Box::new(TomlWriter::new(writer, &config.row_name)),
+        OutputFormat::Xml => Box::new(XmlWriter::try_new(
+            writer,
+            &config.root_name,
+            &config.row_name,
+        )?),
+        OutputFormat::Markdown => Box::new(MarkdownWriter::try_new(writer, columns)?),
+        OutputFormat::Html => Box::new(HtmlWriter::try_new(writer, columns)?),
+        f => anyhow::bail!("{} is a binary format, write it to a file with --output", f),
+    };
+    Ok(writer)
+}
+
+/// serialize records the way the record writer of the format writes them, but without
+/// what opens or closes the document, so they can be written with `write_chunk`.
+/// json and toml records all start with the separator that goes between two records,
+/// the writer drops it in front of the first one
+pub fn serialize_records(
+    format: OutputFormat,
+    columns: &[String],
+    config: &WriterConfig,
+    records: &[Value],
+) -> Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    let writer = &mut buf;
+    let mut writer: Box<dyn RecordWriter + '_> = match format {
+        OutputFormat::Json => Box::new(JsonArrayWriter { writer, count: 1 }),
+        OutputFormat::Ndjson => Box::new(NdjsonWriter::new(writer)),
+        OutputFormat::Yaml => Box::new(YamlWriter::new(writer)),
+        OutputFormat::Csv => Box::new(CsvRecordWriter::without_header(
+            writer,
+            columns,
+            config.delimiter,
+        )),
+        OutputFormat::Toml => Box::new(TomlWriter {
+            count: 1,
+            ..TomlWriter::new(writer, &config.row_name)
+        }),
+        OutputFormat::Xml => Box::new(XmlWriter {
+            writer,
+            root: String::new(),
+            row: xml_name(&config.row_name),
+        }),
+        OutputFormat::Markdown => Box::new(MarkdownWriter {
+            writer,
+            columns: columns.to_vec(),
+        }),
+        OutputFormat::Html => Box::new(HtmlWriter {
+            writer,
+            columns: columns.to_vec(),
+        }),
+        f => anyhow::bail!("{} is a binary format and can't be serialized in chunks", f),
+    };
+    for record in records {
+        writer.write_record(record)?;
+    }
+    // 不能调finish，那会写出文档的结尾；csv::Writer的缓冲在drop时写进buf
+    drop(writer);
+    Ok(buf)
+}
+
+impl<W: Write> JsonArrayWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer, count: 0 }
+    }
+}
+
+impl<W: Write> RecordWriter for JsonArrayWriter<W> {
+    // 输出和serde_json::to_string_pretty整个数组的结果逐字节相同：
+    // JSON字符串里的换行一定是转义过的，所以直接给每一行加两个空格的缩进是安全的
+    fn write_record(&mut self, record: &Value) -> Result<()> {
+        let sep = if self.count == 0 { "[" } else { "," };
+        let pretty = serde_json::to_string_pretty(record)?.replace('\n', "\n  ");
+        write!(self.writer, "{}\n  {}", sep, pretty)?;
+        self.count += 1;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        if self.count == 0 {
+            write!(self.writer, "[]")?;
+        } else {
+            write!(self.writer, "\n]")?;
+        }
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    // 每条记录都以","开头，第一条要换成"["
+    fn write_chunk(&mut self, chunk: &[u8], records: usize) -> Result<()> {
+        if records == 0 {
+            return Ok(());
+        }
+        if self.count == 0 {
+            self.writer.write_all(b"[")?;
+            self.writer.write_all(&chunk[1..])?;
+        } else {
+            self.writer.write_all(chunk)?;
+        }
+        self.count += records;
+        Ok(())
+    }
+}
+
+impl<W: Write> NdjsonWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+}
+
+impl<W: Write> RecordWriter for NdjsonWriter<W> {
+    fn write_record(&mut self, record: &Value) -> Result<()> {
+        serde_json::to_writer(&mut self.writer, record)?;
+        self.writer.write_all(b"\n")?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    fn write_chunk(&mut self, chunk: &[u8], _records: usize) -> Result<()> {
+        self.writer.write_all(chunk)?;
+        Ok(())
+    }
+}
+
+impl<W: Write> YamlWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer, count: 0 }
+    }
+}
+
+impl<W: Write> RecordWriter for YamlWriter<W> {
+    // 一个元素的序列序列化出来就是"- key: value\n  ..."，拼在一起就是整个序列
+    fn write_record(&mut self, record: &Value) -> Result<()> {
+        // 先序列化成字符串，写出错时保留io错误（比如管道被关掉）
+        let yaml = serde_yaml::to_string(&[record])?;
+        self.writer.write_all(yaml.as_bytes())?;
+        self.count += 1;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        if self.count == 0 {
+            self.writer.write_all(b"[]\n")?;
+        }
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    fn write_chunk(&mut self, chunk: &[u8], records: usize) -> Result<()> {
+        self.writer.write_all(chunk)?;
+        self.count += records;
+        Ok(())
+    }
+}
+
+impl<W: Write> CsvRecordWriter<W> {
+    pub fn try_new(writer: W, columns: &[String], delimiter: u8) -> Result<Self> {
+        let mut ret = Self::without_header(writer, columns, delimiter);
+        ret.csv().write_record(columns)?;
+        Ok(ret)
+    }
+
+    fn without_header(writer: W, columns: &[String], delimiter: u8) -> Self {
+        Self {
+            writer: Some(
+                WriterBuilder::new()
+                    .delimiter(delimiter)
+                    .from_writer(writer),
+            ),
+            columns: columns.to_vec(),
+            delimiter,
+        }
+    }
+
+    fn csv(&mut self) -> &mut csv::Writer<W> {
+        self.writer
+            .as_mut()
+            .expect("the csv writer is put back by write_chunk")
+    }
+}
+
+impl<W: Write> RecordWriter for CsvRecordWriter<W> {
+    fn write_record(&mut self, record: &Value) -> Result<()> {
+        let row = self
+            .columns
+            .iter()
+            .map(|col| record.get(col).map(value_to_cell).unwrap_or_default());
+        self.writer
+            .as_mut()
+            .expect("the csv writer is put back by write_chunk")
+            .write_record(row)?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.csv().flush()?;
+        Ok(())
+    }
+
+    // csv::Writer拿不到底层writer的可变引用，先拆开写完再重新包起来
+    fn write_chunk(&mut self, chunk: &[u8], _records: usize) -> Result<()> {
+        let Some(writer) = self.writer.take() else {
+            anyhow::bail!("the csv writer failed before");
+        };
+        let mut inner = writer.into_inner().map_err(|e| e.into_error())?;
+        inner.write_all(chunk)?;
+        self.writer = Some(
+            WriterBuilder::new()
+                .delimiter(self.delimiter)
+                .from_writer(inner),
+        );
+        Ok(())
+    }
+}
+
+impl<W: Write> TomlWriter<W> {
+    pub fn new(writer: W, table_name: &str) -> Self {
+        Self {
+            writer,
+            table_name: table_name.to_string(),
+            count: 0,
+        }
+    }
+}
+
+impl<W: Write> RecordWriter for TomlWriter<W> {
+    // 每条记录都序列化成只有一个元素的[[row]]，拼起来就是完整的array of tables
+    fn write_record(&mut self, record: &Value) -> Result<()> {
+        let row = json_to_toml(record)
+            .ok_or_else(|| anyhow::anyhow!("record is not a table: {}", record))?;
+        let mut doc = toml::Table::new();
+        doc.insert(self.table_name.clone(), toml::Value::Array(vec![row]));
+        if self.count > 0 {
+            self.writer.write_all(b"\n")?;
+        }
+        self.writer.write_all(toml::to_string(&doc)?.as_bytes())?;
+        self.count += 1;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    // 每条记录前面都有一个空行，第一条要去掉
+    fn write_chunk(&mut self, chunk: &[u8], records: usize) -> Result<()> {
+        if records == 0 {
+            return Ok(());
+        }
+        let chunk = if self.count == 0 { &chunk[1..] } else { chunk };
+        self.writer.write_all(chunk)?;
+        self.count += records;
+        Ok(())
+    }
+}
+
+// toml没有null，值为null的key直接省略
+fn json_to_toml(value: &Value) -> Option<toml::Value> {
+    let value = match value {
+        Value::Null => return None,
+        Value::Bool(b) => toml::Value::Boolean(*b),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => toml::Value::Integer(i),
+            None => toml::Value::Float(n.as_f64()?),
+        },
+        Value::String(s) => toml::Value::String(s.clone()),
+        Value::Array(arr) => toml::Value::Array(arr.iter().filter_map(json_to_toml).collect()),
+        Value::Object(obj) => toml::Value::Table(
+            obj.iter()
+                .filter_map(|(k, v)| Some((k.clone(), json_to_toml(v)?)))
+                .collect(),
+        ),
+    };
+    Some(value)
+}
+
+impl<W: Write> XmlWriter<W> {
+    pub fn try_new(mut writer: W, root: &str, row: &str) -> Result<Self> {
+        let root = xml_name(root);
+        writeln!(writer, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+        writeln!(writer, "<{}>", root)?;
+        Ok(Self {
+            writer,
+            root,
+            row: xml_name(row),
+        })
+    }
+
+    fn write_element(&mut self, name: &str, value: &Value, depth: usize) -> Result<()> {
+        let indent = "  ".repeat(depth);
+        match value {
+            Value::Null => writeln!(self.writer, "{}<{}/>", indent, name)?,
+            // 数组用同名元素重复表示
+            Value::Array(arr) => {
+                for v in arr {
+                    self.write_element(name, v, depth)?;
+                }
+            }
+            Value::Object(obj) => {
+                writeln!(self.writer, "{}<{}>", indent, name)?;
+                for (k, v) in obj {
+                    self.write_element(&xml_name(k), v, depth + 1)?;
+                }
+                writeln!(self.writer, "{}</{}>", indent, name)?;
+            }
+            v => writeln!(
+                self.writer,
+                "{}<{}>{}</{}>",
+                indent,
+                name,
+                escape_html(&value_to_cell(v)),
+                name
+            )?,
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> RecordWriter for XmlWriter<W> {
+    fn write_record(&mut self, record: &Value) -> Result<()> {
+        let row = self.row.clone();
+        self.write_element(&row, record, 1)
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        writeln!(self.writer, "</{}>", self.root)?;
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    fn write_chunk(&mut self, chunk: &[u8], _records: usize) -> Result<()> {
+        self.writer.write_all(chunk)?;
+        Ok(())
+    }
+}
+
+/// turn a column name into a valid xml element name, e.g. "Kit Number" -> "Kit_Number"
+fn xml_name(name: &str) -> String {
+    let mut ret: String = name
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || matches!(c, '_' | '-' | '.') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if !ret.starts_with(|c: char| c.is_alphabetic() || c == '_') {
+        ret.insert(0, '_');
+    }
+    ret
+}
+
+fn escape_html(s: &str) -> String {
+    let mut ret = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => ret.push_str("&amp;"),
+            '<' => ret.push_str("&lt;"),
+            '>' => ret.push_str("&gt;"),
+            '"' => ret.push_str("&quot;"),
+            '\'' => ret.push_str("&#39;"),
+            c => ret.push(c),
+        }
+    }
+    ret
+}
+
+impl<W: Write> MarkdownWriter<W> {
+    pub fn try_new(mut writer: W, columns: &[String]) -> Result<Self> {
+        let header: Vec<String> = columns.iter().map(|c| escape_markdown(c)).collect();
+        writeln!(writer, "| {} |", header.join(" | "))?;
+        writeln!(writer, "|{}", " --- |".repeat(columns.len()))?;
+        Ok(Self {
+            writer,
+            columns: columns.to_vec(),
+        })
+    }
+}
+
+impl<W: Write> RecordWriter for MarkdownWriter<W> {
+    fn write_record(&mut self, record: &Value) -> Result<()> {
+        let cells: Vec<String> = self
+            .columns
+            .iter()
+            .map(|col| escape_markdown(&record.get(col).map(value_to_cell).unwrap_or_default()))
+            .collect();
+        writeln!(self.writer, "| {} |", cells.join(" | "))?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    fn write_chunk(&mut self, chunk: &[u8], _records: usize) -> Result<()> {
+        self.writer.write_all(chunk)?;
+        Ok(())
+    }
+}
+
+// 表格里的|和换行会破坏GFM表格的结构；\要先转义，否则结尾的\会把分隔的|转义掉。
+// <和>（连同&）转义成实体，单元格里的文本不能被当成html
+fn escape_markdown(s: &str) -> String {
+    let mut ret = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => ret.push_str("\\\\"),
+            '|' => ret.push_str("\\|"),
+            '&' => ret.push_str("&amp;"),
+            '<' => ret.push_str("&lt;"),
+            '>' => ret.push_str("&gt;"),
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' => ret.push_str("<br>"),
+            c => ret.push(c),
+        }
+    }
+    ret
+}
+
+impl<W: Write> HtmlWriter<W> {
+    pub fn try_new(mut writer: W, columns: &[String]) -> Result<Self> {
+        writeln!(writer, "<!DOCTYPE html>")?;
+        writeln!(writer, "<html>")?;
+        writeln!(writer, "<head>")?;
+        writeln!(writer, "<meta charset=\"utf-8\">")?;
+        writeln!(
+            writer,
+            "<style>table {{ border-collapse: collapse; }} th, td {{ border: 1px solid #ccc; padding: 4px 8px; }}</style>"
+        )?;
+        writeln!(writer, "</head>")?;
+        writeln!(writer, "<body>")?;
+        writeln!(writer, "<table>")?;
+        writeln!(writer, "<thead>")?;
+        write!(writer, "<tr>")?;
+        for col in columns {
+            write!(writer, "<th>{}</th>", escape_html(col))?;
+        }
+        writeln!(writer, "</tr>")?;
+        writeln!(writer, "</thead>")?;
+        writeln!(writer, "<tbody>")?;
+        Ok(Self {
+            writer,
+            columns: columns.to_vec(),
+        })
+    }
+}
+
+impl<W: Write> RecordWriter for HtmlWriter<W> {
+    fn write_record(&mut self, record: &Value) -> Result<()> {
+        write!(self.writer, "<tr>")?;
+        for col in &self.columns {
+            let cell = record.get(col).map(value_to_cell).unwrap_or_default();
+            write!(self.writer, "<td>{}</td>", escape_html(&cell))?;
+        }
+        writeln!(self.writer, "</tr>")?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        writeln!(self.writer, "</tbody>")?;
+        writeln!(self.writer, "</table>")?;
+        writeln!(self.writer, "</body>")?;
+        writeln!(self.writer, "</html>")?;
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    fn write_chunk(&mut self, chunk: &[u8], _records: usize) -> Result<()> {
+        self.writer.write_all(chunk)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn write_all(format: OutputFormat, records: &[Value]) -> Result<String> {
+        let mut buf = Vec::new();
+        let columns = ["a".to_string(), "b".to_string()];
+        let mut writer = record_writer(format, &columns, &WriterConfig::default(), &mut buf)?;
+        for record in records {
+            writer.write_record(record)?;
+        }
+        writer.finish()?;
+        drop(writer);
+        Ok(String::from_utf8(buf)?)
+    }
+
+    #[test]
+    fn test_streaming_matches_whole_document() -> Result<()> {
+        let records = vec![
+            json!({"a": 1, "b": {"c": [1, 2]}}),
+            json!({"a": "x\ny", "b": null}),
+        ];
+        for records in [&records[..], &[]] {
+            assert_eq!(
+                write_all(OutputFormat::Json, records)?,
+                serde_json::to_string_pretty(records)?
+            );
+            assert_eq!(
+                write_all(OutputFormat::Yaml, records)?,
+                serde_yaml::to_string(records)?
+            );
+        }
+        assert_eq!(
+            write_all(OutputFormat::Ndjson, &records)?,
+            "{\"a\":1,\"b\":{\"c\":[1,2]}}\n{\"a\":\"x\\ny\",\"b\":null}\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_table_formats() -> Result<()> {
+        let records = vec![json!({"a": "x|y", "b": 1}), json!({"a": "<b>", "b": null})];
+        assert_eq!(
+            write_all(OutputFormat::Markdown, &records)?,
+            "| a | b |\n| --- | --- |\n| x\\|y | 1 |\n| &lt;b&gt; |  |\n"
+        );
+        // 结尾的\不能把后面的|转义掉
+        let backslash = vec![json!({"a": "C:\\", "b": "1\r\n2"})];
+        assert_eq!(
+            write_all(OutputFormat::Markdown, &backslash)?,
+            "| a | b |\n| --- | --- |\n| C:\\\\ | 1<br>2 |\n"
+        );
+        assert_eq!(
+            write_all(OutputFormat::Toml, &records)?,
+            "[[row]]\na = \"x|y\"\nb = 1\n\n[[row]]\na = \"<b>\"\n"
+        );
+        let xml = write_all(OutputFormat::Xml, &records)?;
+        assert!(xml.contains("  <row>\n    <a>&lt;b&gt;</a>\n    <b/>\n  </row>\n</rows>\n"));
+        Ok(())
+    }
+}
@@ -0,0 +1,539 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Write,
+};
+
+use anyhow::Result;
+use chrono::{
+    format::{Item, StrftimeItems},
+    Duration, NaiveDate,
+};
+use rand::{distributions::WeightedIndex, prelude::*, rngs::StdRng};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+
+use crate::{cli::CsvOutputOpts, get_content};
+
+use super::{
+    csv_convert::value_to_cell,
+    csv_writer::{output_record_writer, WriterConfig},
+};
+
+const PLAYER_SCHEMA: &str = include_str!("../../assets/player_schema.yaml");
+
+// 非int的unique列，每行最多重试这么多次
+const UNIQUE_RETRIES: usize = 1000;
+
+const FIRST_NAMES: &[&str] = &[
+    "Alessandro",
+    "Amara",
+    "Andrea",
+    "Antoine",
+    "Ayumi",
+    "Camila",
+    "Carlos",
+    "Chen",
+    "Chiara",
+    "Daniel",
+    "David",
+    "Elena",
+    "Emma",
+    "Federico",
+    "Giulia",
+    "Hannah",
+    "Hiroshi",
+    "Ibrahim",
+    "Isabel",
+    "Jakub",
+    "João",
+    "Julia",
+    "Kwame",
+    "Lars",
+    "Laura",
+    "Leon",
+    "Lucas",
+    "Lucía",
+    "Marco",
+    "Maria",
+    "Mateo",
+    "Mohamed",
+    "Nadia",
+    "Nikola",
+    "Olivia",
+    "Omar",
+    "Pablo",
+    "Paulo",
+    "Pedro",
+    "Priya",
+    "Rafael",
+    "Sami",
+    "Sara",
+    "Sofia",
+    "Tomás",
+    "Wei",
+    "Wojciech",
+    "Yasmin",
+    "Youssef",
+    "Zoe",
+];
+
+const LAST_NAMES: &[&str] = &[
+    "Andersen",
+    "Bianchi",
+    "Costa",
+    "Diallo",
+    "Dubois",
+    "Esposito",
+    "Fernandes",
+    "Ferrari",
+    "García",
+    "Hansen",
+    "Ivanović",
+    "Jansen",
+    "Kowalski",
+    "Lambert",
+    "Li",
+    "López",
+    "Mensah",
+    "Moreau",
+    "Müller",
+    "Nakamura",
+    "Nielsen",
+    "Novak",
+    "Okafor",
+    "Oliveira",
+    "Petrović",
+    "Ricci",
+    "Rodríguez",
+    "Romano",
+    "Rossi",
+    "Santos",
+    "Schmidt",
+    "Silva",
+    "Smith",
+    "Suzuki",
+    "Traoré",
+    "Van Dijk",
+    "Wang",
+    "Weber",
+    "Wilson",
+    "Yilmaz",
+];
+
+const NATIONALITIES: &[&str] = &[
+    "Argentina",
+    "Belgium",
+    "Bosnia-Herzegovina",
+    "Brazil",
+    "Cameroon",
+    "Canada",
+    "Chile",
+    "China",
+    "Colombia",
+    "Croatia",
+    "Czech Republic",
+    "Denmark",
+    "England",
+    "France",
+    "Germany",
+    "Ghana",
+    "Greece",
+    "Italy",
+    "Ivory Coast",
+    "Japan",
+    "Mexico",
+    "Morocco",
+    "Netherlands",
+    "Nigeria",
+    "Norway",
+    "Poland",
+    "Portugal",
+    "Senegal",
+    "Serbia",
+    "Spain",
+    "Sweden",
+    "Switzerland",
+    "Turkey",
+    "United States",
+    "Uruguay",
+    "Wales",
+];
+
+/// a fake data schema, e.g.
+///
+/// ```yaml
+/// columns:
+///   - name: id
+///     type: id
+///     prefix: P-
+///   - name: Kit Number
+///     type: int
+///     min: 1
+///     max: 99
+///     unique: true
+///   - name: Position
+///     type: enum
+///     values: [Goalkeeper, Defender, Midfielder, Forward]
+///     weights: [1, 4, 4, 2]
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FakeSchema {
+    pub columns: Vec<FakeColumn>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct FakeColumn {
+    pub name: String,
+    /// no two rows get the same value
+    #[serde(default)]
+    pub unique: bool,
+    #[serde(flatten)]
+    pub kind: FakeKind,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum FakeKind {
+    /// first and last name
+    Name,
+    FirstName,
+    LastName,
+    Nationality,
+    Bool,
+    /// inclusive range
+    Int {
+        min: i64,
+        max: i64,
+    },
+    Float {
+        min: f64,
+        max: f64,
+        #[serde(default = "default_decimals")]
+        decimals: u32,
+    },
+    /// a day between min and max, both YYYY-MM-DD, written with a chrono format
+    Date {
+        min: String,
+        max: String,
+        #[serde(default = "default_date_format")]
+        format: String,
+    },
+    /// one of the values, optionally weighted
+    Enum {
+        values: Vec<Value>,
+        #[serde(default)]
+        weights: Vec<f64>,
+    },
+    /// sequential ids, always unique
+    Id {
+        #[serde(default = "default_start")]
+        start: u64,
+        #[serde(default)]
+        prefix: String,
+    },
+}
+
+/// generates rows of a schema, deterministic for a given seed
+pub struct FakeGenerator {
+    columns: Vec<ColumnGenerator>,
+    rng: StdRng,
+    row: u64,
+}
+
+struct ColumnGenerator {
+    name: String,
+    kind: Kind,
+    unique: Option<HashSet<String>>,
+}
+
+enum Kind {
+    Name,
+    FirstName,
+    LastName,
+    Nationality,
+    Bool,
+    Int(i64, i64),
+    // 不重复的整数：稀疏的Fisher-Yates洗牌，内存只和已生成的行数有关
+    UniqueInt {
+        min: i64,
+        len: u64,
+        drawn: u64,
+        swapped: HashMap<u64, u64>,
+    },
+    Float(f64, f64, u32),
+    Date(NaiveDate, i64, String),
+    Enum(Vec<Value>, Option<WeightedIndex<f64>>),
+    Id(u64, String),
+}
+
+pub fn process_fake(
+    schema: Option<&str>,
+    rows: usize,
+    seed: Option<u64>,
+    target: &CsvOutputOpts,
+) -> Result<()> {
+    let schema: FakeSchema = match schema {
+        Some(path) => serde_yaml::from_slice(&get_content(path)?)?,
+        None => serde_yaml::from_str(PLAYER_SCHEMA)?,
+    };
+    let mut generator = FakeGenerator::new(schema, seed)?;
+    let columns = generator.columns();
+    let config = WriterConfig {
+        root_name: target.root_name.clone(),
+        row_name: target.row_name.clone(),
+        ..Default::default()
+    };
+    let mut writer =
+        output_record_writer(target.format, &columns, &config, target.output.as_deref())?;
+    for _ in 0..rows {
+        writer.write_record(&generator.next_record()?)?;
+    }
+    writer.finish()?;
+    Ok(())
+}
+
+impl FakeGenerator {
+    pub fn new(schema: FakeSchema, seed: Option<u64>) -> Result<Self> {
+        let columns = schema
+            .columns
+            .into_iter()
+            .map(ColumnGenerator::new)
+            .collect::<Result<Vec<_>>>()?;
+        let rng = match seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        Ok(Self {
+            columns,
+            rng,
+            row: 0,
+        })
+    }
+
+    pub fn columns(&self) -> Vec<String> {
+        self.columns.iter().map(|c| c.name.clone()).collect()
+    }
+
+    pub fn next_record(&mut self) -> Result<Value> {
+        let mut map = Map::with_capacity(self.columns.len());
+        for column in self.columns.iter_mut() {
+            let value = column.next_value(&mut self.rng, self.row)?;
+            map.insert(column.name.clone(), value);
+        }
+        self.row += 1;
+        Ok(Value::Object(map))
+    }
+}
+
+impl ColumnGenerator {
+    fn new(column: FakeColumn) -> Result<Self> {
+        let name = column.name;
+        let invalid = |msg: &str| anyhow::anyhow!("Column {} in schema: {}", name, msg);
+        let kind = match column.kind {
+            FakeKind::Name => Kind::Name,
+            FakeKind::FirstName => Kind::FirstName,
+            FakeKind::LastName => Kind::LastName,
+            FakeKind::Nationality => Kind::Nationality,
+            FakeKind::Bool => Kind::Bool,
+            FakeKind::Int { min, max } if min > max => return Err(invalid("min is above max")),
+            FakeKind::Int { min, max } if column.unique => Kind::UniqueInt {
+                min,
+                len: (max as i128 - min as i128 + 1)
+                    .try_into()
+                    .unwrap_or(u64::MAX),
+                drawn: 0,
+                swapped: HashMap::new(),
+            },
+            FakeKind::Int { min, max } => Kind::Int(min, max),
+            // NaN和任何值比较都是false，要先单独排除
+            FakeKind::Float { min, max, .. } if !min.is_finite() || !max.is_finite() => {
+                return Err(invalid("min and max must be finite numbers"))
+            }
+            FakeKind::Float { min, max, .. } if min > max => {
+                return Err(invalid("min is above max"))
+            }
+            FakeKind::Float { min, max, decimals } => Kind::Float(min, max, decimals),
+            FakeKind::Date { min, max, format } => {
+                let parse = |s: &str| {
+                    NaiveDate::parse_from_str(s, "%Y-%m-%d")
+                        .map_err(|_| invalid(&format!("invalid date {}, expect YYYY-MM-DD", s)))
+                };
+                let (min, max) = (parse(&min)?, parse(&max)?);
+                if min > max {
+                    return Err(invalid("min is after max"));
+                }
+                // 非法的格式在to_string时会panic，这里先检查；%H这类日期里没有的字段也格式化不出来
+                let mut sample = String::new();
+                if StrftimeItems::new(&format).any(|item| matches!(item, Item::Error))
+                    || write!(sample, "{}", min.format(&format)).is_err()
+                {
+                    return Err(invalid(&format!("invalid date format {}", format)));
+                }
+                Kind::Date(min, (max - min).num_days(), format)
+            }
+            FakeKind::Enum { values, .. } if values.is_empty() => {
+                return Err(invalid("enum needs at least one value"))
+            }
+            FakeKind::Enum { values, weights } if weights.is_empty() => Kind::Enum(values, None),
+            FakeKind::Enum { weights, values } => {
+                if weights.len() != values.len() {
+                    return Err(invalid("weights and values must have the same length"));
+                }
+                let index = WeightedIndex::new(&weights).map_err(|e| invalid(&e.to_string()))?;
+                Kind::Enum(values, Some(index))
+            }
+            FakeKind::Id { start, prefix } => Kind::Id(start, prefix),
+        };
+        // int和id本身就不会重复，其他类型靠记住生成过的值
+        let unique = match kind {
+            Kind::UniqueInt { .. } | Kind::Id(..) => None,
+            _ if column.unique => Some(HashSet::new()),
+            _ => None,
+        };
+        Ok(Self { name, kind, unique })
+    }
+
+    fn next_value(&mut self, rng: &mut StdRng, row: u64) -> Result<Value> {
+        let Some(seen) = self.unique.as_mut() else {
+            return self.kind.generate(rng, row, &self.name);
+        };
+        for _ in 0..UNIQUE_RETRIES {
+            let value = self.kind.generate(rng, row, &self.name)?;
+            if seen.insert(value_to_cell(&value)) {
+                return Ok(value);
+            }
+        }
+        anyhow::bail!(
+            "Column {}: no new unique value after {} rows",
+            self.name,
+            seen.len()
+        )
+    }
+}
+
+impl Kind {
+    fn generate(&mut self, rng: &mut StdRng, row: u64, column: &str) -> Result<Value> {
+        Ok(match self {
+            Kind::Name => json!(format!(
+                "{} {}",
+                pick(rng, FIRST_NAMES),
+                pick(rng, LAST_NAMES)
+            )),
+            Kind::FirstName => json!(pick(rng, FIRST_NAMES)),
+            Kind::LastName => json!(pick(rng, LAST_NAMES)),
+            Kind::Nationality => json!(pick(rng, NATIONALITIES)),
+            Kind::Bool => json!(rng.gen_bool(0.5)),
+            Kind::Int(min, max) => json!(rng.gen_range(*min..=*max)),
+            Kind::UniqueInt {
+                min,
+                len,
+                drawn,
+                swapped,
+            } => {
+                if *drawn >= *len {
+                    anyhow::bail!("Column {}: only {} unique values in range", column, len);
+                }
+                let i = *drawn;
+                let j = rng.gen_range(i..*len);
+                let picked = swapped.get(&j).copied().unwrap_or(j);
+                swapped.insert(j, swapped.get(&i).copied().unwrap_or(i));
+                swapped.remove(&i);
+                *drawn += 1;
+                json!((*min as i128 + picked as i128) as i64)
+            }
+            Kind::Float(min, max, decimals) => {
+                let value = if min == max {
+                    *min
+                } else {
+                    rng.gen_range(*min..=*max)
+                };
+                let scale = 10f64.powi(*decimals as i32);
+                json!((value * scale).round() / scale)
+            }
+            Kind::Date(min, days, format) => {
+                let date = *min + Duration::days(rng.gen_range(0..=*days));
+                json!(date.format(format).to_string())
+            }
+            Kind::Enum(values, None) => values[rng.gen_range(0..values.len())].clone(),
+            Kind::Enum(values, Some(index)) => values[index.sample(rng)].clone(),
+            Kind::Id(start, prefix) if prefix.is_empty() => json!(*start + row),
+            Kind::Id(start, prefix) => json!(format!("{}{}", prefix, *start + row)),
+        })
+    }
+}
+
+fn pick<'a>(rng: &mut StdRng, values: &[&'a str]) -> &'a str {
+    values[rng.gen_range(0..values.len())]
+}
+
+fn default_decimals() -> u32 {
+    2
+}
+
+fn default_date_format() -> String {
+    "%Y-%m-%d".to_string()
+}
+
+fn default_start() -> u64 {
+    1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process::csv_convert::Player;
+
+    fn player_rows(seed: u64, rows: usize) -> Result<Vec<Value>> {
+        let schema = serde_yaml::from_str(PLAYER_SCHEMA)?;
+        let mut generator = FakeGenerator::new(schema, Some(seed))?;
+        (0..rows).map(|_| generator.next_record()).collect()
+    }
+
+    #[test]
+    fn test_player_schema() -> Result<()> {
+        let rows = player_rows(7, 50)?;
+        assert_eq!(rows, player_rows(7, 50)?);
+        assert_ne!(rows, player_rows(8, 50)?);
+        for row in rows {
+            let _: Player = serde_json::from_value(row)?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_unique_int() -> Result<()> {
+        let schema: FakeSchema = serde_yaml::from_str(
+            "columns:\n  - {name: kit, type: int, min: 1, max: 20, unique: true}\n",
+        )?;
+        let mut generator = FakeGenerator::new(schema, Some(1))?;
+        let mut kits: Vec<i64> = (0..20)
+            .map(|_| Ok(generator.next_record()?["kit"].as_i64().unwrap()))
+            .collect::<Result<_>>()?;
+        kits.sort();
+        assert_eq!(kits, (1..=20).collect::<Vec<_>>());
+        assert!(generator.next_record().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_schema() {
+        for format in ["%Q", "%H:%M"] {
+            let schema: FakeSchema = serde_yaml::from_str(&format!(
+                "columns:\n  - {{name: dob, type: date, min: 1990-01-01, max: 2000-01-01, format: \"{}\"}}\n",
+                format
+            ))
+            .unwrap();
+            let err = FakeGenerator::new(schema, Some(1)).err().unwrap();
+            assert!(err.to_string().starts_with("Column dob in schema"));
+        }
+        for bounds in ["min: .nan, max: 1", "min: 0, max: .inf"] {
+            let schema: FakeSchema = serde_yaml::from_str(&format!(
+                "columns:\n  - {{name: rating, type: float, {}}}\n",
+                bounds
+            ))
+            .unwrap();
+            assert!(FakeGenerator::new(schema, Some(1)).is_err());
+        }
+    }
+}
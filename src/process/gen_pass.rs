@@ -1,11 +1,45 @@
-use anyhow::Ok;
-use rand::seq::SliceRandom;
+use rand::{seq::SliceRandom, Rng};
 
 const UPPER: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ";
 const LOWER: &[u8] = b"abcdefghijkmnopqrstuvwxyz";
 const NUMBER: &[u8] = b"23456789";
 const SYMBOL: &[u8] = b"!@#$%^&*_";
 
+/// what a password should look like, before it is checked and turned into `PasswordRules`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PasswordSpec {
+    pub length: usize,
+    pub upper: bool,
+    pub lower: bool,
+    pub number: bool,
+    pub symbol: bool,
+    /// replaces the symbols above when set
+    pub symbol_set: Option<String>,
+    /// draw from these characters instead of the enabled classes
+    pub charset: Option<String>,
+    /// characters that are never used
+    pub exclude: String,
+    /// minimum count of each class, 1 for every class with characters when not set
+    pub min_upper: Option<usize>,
+    pub min_lower: Option<usize>,
+    pub min_digits: Option<usize>,
+    pub min_symbols: Option<usize>,
+}
+
+/// the characters of a password split into classes, each with a minimum count
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PasswordRules {
+    pub length: usize,
+    pub classes: Vec<CharClass>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CharClass {
+    pub name: &'static str,
+    pub chars: Vec<char>,
+    pub min: usize,
+}
+
 pub fn process_genpass(
     length: u8,
     upper: bool,
@@ -13,50 +47,238 @@ pub fn process_genpass(
     number: bool,
     symbol: bool,
 ) -> anyhow::Result<String> {
-    let mut rng = rand::thread_rng();
-    let mut password = Vec::new();
-    let mut chars = Vec::new();
+    let spec = PasswordSpec {
+        upper,
+        lower,
+        number,
+        symbol,
+        ..PasswordSpec::new(length as usize)
+    };
+    let rules = spec.rules()?;
+    Ok(generate_password(&rules, &mut rand::thread_rng()))
+}
 
-    if upper {
-        chars.extend_from_slice(UPPER);
-        password.push(
-            *UPPER
-                .choose(&mut rng)
-                .expect("UPPER won't be empty in this context"),
-        );
+/// the required characters of each class, then the rest from all classes, shuffled
+pub fn generate_password(rules: &PasswordRules, rng: &mut impl Rng) -> String {
+    let mut password = Vec::with_capacity(rules.length);
+    for class in &rules.classes {
+        for _ in 0..class.min {
+            password.push(
+                *class
+                    .chars
+                    .choose(rng)
+                    .expect("class won't be empty in this context"),
+            );
+        }
     }
-    if lower {
-        chars.extend_from_slice(LOWER);
+    let chars = rules.chars();
+    while password.len() < rules.length {
         password.push(
-            *LOWER
-                .choose(&mut rng)
-                .expect("LOWER won't be empty in this context"),
+            *chars
+                .choose(rng)
+                .expect("chars won't be empty in this context"),
         );
     }
-    if number {
-        chars.extend_from_slice(NUMBER);
-        password.push(
-            *NUMBER
-                .choose(&mut rng)
-                .expect("NUMBER won't be empty in this context"),
-        );
+    password.shuffle(rng);
+    password.into_iter().collect()
+}
+
+impl PasswordSpec {
+    pub fn new(length: usize) -> Self {
+        Self {
+            length,
+            upper: true,
+            lower: true,
+            number: true,
+            symbol: true,
+            symbol_set: None,
+            charset: None,
+            exclude: String::new(),
+            min_upper: None,
+            min_lower: None,
+            min_digits: None,
+            min_symbols: None,
+        }
     }
-    if symbol {
-        chars.extend_from_slice(SYMBOL);
-        password.push(
-            *SYMBOL
-                .choose(&mut rng)
-                .expect("SYMBOL won't be empty in this context"),
-        );
+
+    /// check that the constraints can all be met
+    pub fn rules(&self) -> anyhow::Result<PasswordRules> {
+        if self.length == 0 {
+            anyhow::bail!("Password length must be greater than 0");
+        }
+        let alphabet = self.alphabet();
+        if alphabet.is_empty() {
+            anyhow::bail!("No characters left to generate a password from");
+        }
+
+        let mut classes = Vec::new();
+        for (name, flag, min) in [
+            ("uppercase", "--min-upper", self.min_upper),
+            ("lowercase", "--min-lower", self.min_lower),
+            ("digit", "--min-digits", self.min_digits),
+            ("symbol", "--min-symbols", self.min_symbols),
+        ] {
+            let chars: Vec<char> = alphabet
+                .iter()
+                .copied()
+                .filter(|c| class_of(*c) == name)
+                .collect();
+            let min = match min {
+                Some(n) if n > 0 && chars.is_empty() => anyhow::bail!(
+                    "{} {} needs {} characters, but none are left",
+                    flag,
+                    n,
+                    name
+                ),
+                Some(n) => n,
+                None => usize::from(!chars.is_empty()),
+            };
+            if !chars.is_empty() {
+                classes.push(CharClass { name, chars, min });
+            }
+        }
+        let required: usize = classes.iter().map(|c| c.min).sum();
+        if required > self.length {
+            anyhow::bail!(
+                "The minimums add up to {} characters, more than the length {}",
+                required,
+                self.length
+            );
+        }
+        Ok(PasswordRules {
+            length: self.length,
+            classes,
+        })
     }
-    for _ in 0..length - password.len() as u8 {
-        let c = chars
-            .choose(&mut rng)
-            .expect("chars won't be empty in this context");
-        password.push(*c);
+
+    /// the digits and the symbols of the characters `rules` would use
+    pub fn digits_and_symbols(&self) -> (Vec<char>, Vec<char>) {
+        let alphabet = self.alphabet();
+        let class = |name| {
+            alphabet
+                .iter()
+                .copied()
+                .filter(|c| class_of(*c) == name)
+                .collect()
+        };
+        (class("digit"), class("symbol"))
     }
-    password.shuffle(&mut rng);
-    let password = String::from_utf8(password)?;
 
-    Ok(password)
+    // 字符集或者开启的字符类，去掉排除的字符
+    fn alphabet(&self) -> Vec<char> {
+        let mut alphabet: Vec<char> = match &self.charset {
+            Some(charset) => charset.chars().collect(),
+            None => {
+                let ascii = |chars: &[u8]| chars.iter().map(|&c| c as char).collect::<Vec<_>>();
+                let symbols = match &self.symbol_set {
+                    Some(set) => set.chars().collect(),
+                    None => ascii(SYMBOL),
+                };
+                [
+                    (self.upper, ascii(UPPER)),
+                    (self.lower, ascii(LOWER)),
+                    (self.number, ascii(NUMBER)),
+                    (self.symbol, symbols),
+                ]
+                .into_iter()
+                .filter(|(enabled, _)| *enabled)
+                .flat_map(|(_, chars)| chars)
+                .collect()
+            }
+        };
+        // 自定义的字符集可能有重复，重复的字符会被选中得更多
+        let mut seen = std::collections::HashSet::new();
+        alphabet.retain(|c| !self.exclude.contains(*c) && seen.insert(*c));
+        alphabet
+    }
+}
+
+impl PasswordRules {
+    /// all the characters a password can use
+    pub fn chars(&self) -> Vec<char> {
+        self.classes
+            .iter()
+            .flat_map(|c| c.chars.iter().copied())
+            .collect()
+    }
+
+    /// bits of entropy of a password drawn uniformly from all the characters, the minimums
+    /// per class make the real value slightly lower
+    pub fn entropy(&self) -> f64 {
+        self.length as f64 * (self.chars().len() as f64).log2()
+    }
+}
+
+pub(crate) fn class_of(c: char) -> &'static str {
+    if c.is_uppercase() {
+        "uppercase"
+    } else if c.is_lowercase() {
+        "lowercase"
+    } else if c.is_numeric() {
+        "digit"
+    } else {
+        "symbol"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_password_rules() -> anyhow::Result<()> {
+        let spec = PasswordSpec {
+            symbol_set: Some("!#%".to_string()),
+            exclude: "ABC".to_string(),
+            min_upper: Some(2),
+            min_digits: Some(3),
+            ..PasswordSpec::new(12)
+        };
+        let rules = spec.rules()?;
+        let mins: Vec<(&str, usize)> = rules.classes.iter().map(|c| (c.name, c.min)).collect();
+        assert_eq!(
+            mins,
+            vec![
+                ("uppercase", 2),
+                ("lowercase", 1),
+                ("digit", 3),
+                ("symbol", 1)
+            ]
+        );
+        for _ in 0..20 {
+            let password = generate_password(&rules, &mut rand::thread_rng());
+            assert_eq!(password.chars().count(), 12);
+            assert!(password.chars().filter(|c| c.is_uppercase()).count() >= 2);
+            assert!(password.chars().filter(|c| c.is_ascii_digit()).count() >= 3);
+            assert!(!password.contains(['A', 'B', 'C', '^', '&']));
+        }
+
+        let charset = PasswordSpec {
+            charset: Some("abc123".to_string()),
+            ..PasswordSpec::new(8)
+        };
+        assert_eq!(charset.rules()?.chars().len(), 6);
+        Ok(())
+    }
+
+    #[test]
+    fn test_impossible_rules() {
+        let too_long = PasswordSpec {
+            min_digits: Some(10),
+            ..PasswordSpec::new(8)
+        };
+        assert!(too_long.rules().is_err());
+        let no_upper = PasswordSpec {
+            charset: Some("abc123".to_string()),
+            min_upper: Some(1),
+            ..PasswordSpec::new(8)
+        };
+        assert!(no_upper.rules().is_err());
+        let nothing_left = PasswordSpec {
+            charset: Some("O0l1".to_string()),
+            exclude: "O0l1".to_string(),
+            ..PasswordSpec::new(8)
+        };
+        assert!(nothing_left.rules().is_err());
+    }
 }
@@ -0,0 +1,130 @@
+use std::io::{BufRead, BufReader, Write};
+
+use anyhow::Result;
+use serde::Serialize;
+use zxcvbn::zxcvbn;
+
+use crate::{cli::PassFormat, get_reader};
+
+/// one generated password with its zxcvbn score
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct GeneratedPassword {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    pub password: String,
+    pub score: u8,
+    /// bits of entropy of the generator, not zxcvbn's estimate
+    pub entropy: f64,
+}
+
+impl GeneratedPassword {
+    pub fn new(label: Option<String>, password: String, entropy: f64) -> Self {
+        let score = u8::from(zxcvbn(&password, &[]).score());
+        Self {
+            label,
+            password,
+            score,
+            entropy,
+        }
+    }
+}
+
+/// one label per line, empty lines are skipped
+pub fn load_labels(path: &str) -> Result<Vec<String>> {
+    let reader = BufReader::new(get_reader(path)?);
+    let mut labels = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        let label = line.trim();
+        if !label.is_empty() {
+            labels.push(label.to_string());
+        }
+    }
+    if labels.is_empty() {
+        anyhow::bail!("No labels in {}", path);
+    }
+    Ok(labels)
+}
+
+/// plain writes a password per line, prefixed with its label and a tab when there is one
+pub fn write_passwords(
+    passwords: &[GeneratedPassword],
+    format: PassFormat,
+    mut writer: impl Write,
+) -> Result<()> {
+    match format {
+        PassFormat::Plain => {
+            for p in passwords {
+                match &p.label {
+                    Some(label) => writeln!(writer, "{}\t{}", label, p.password)?,
+                    None => writeln!(writer, "{}", p.password)?,
+                }
+            }
+        }
+        PassFormat::Json => {
+            serde_json::to_writer_pretty(&mut writer, passwords)?;
+            writeln!(writer)?;
+        }
+        PassFormat::Csv => {
+            let mut wtr = csv::Writer::from_writer(&mut writer);
+            // 有一个带label就都带，列保持一致
+            let labeled = passwords.iter().any(|p| p.label.is_some());
+            if labeled {
+                wtr.write_record(["label", "password", "score", "entropy"])?;
+            } else {
+                wtr.write_record(["password", "score", "entropy"])?;
+            }
+            for p in passwords {
+                let mut record = vec![];
+                if labeled {
+                    record.push(p.label.clone().unwrap_or_default());
+                }
+                record.push(p.password.clone());
+                record.push(p.score.to_string());
+                record.push(format!("{:.1}", p.entropy));
+                wtr.write_record(&record)?;
+            }
+            wtr.flush()?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_passwords() -> Result<()> {
+        let passwords = vec![
+            GeneratedPassword {
+                label: Some("router-01".to_string()),
+                password: "x,y".to_string(),
+                score: 0,
+                entropy: 12.345,
+            },
+            GeneratedPassword {
+                label: None,
+                password: "abc".to_string(),
+                score: 0,
+                entropy: 14.1,
+            },
+        ];
+        let mut csv = Vec::new();
+        write_passwords(&passwords, PassFormat::Csv, &mut csv)?;
+        assert_eq!(
+            String::from_utf8(csv)?,
+            "label,password,score,entropy\nrouter-01,\"x,y\",0,12.3\n,abc,0,14.1\n"
+        );
+
+        let mut json = Vec::new();
+        write_passwords(&passwords[1..], PassFormat::Json, &mut json)?;
+        let value: serde_json::Value = serde_json::from_slice(&json)?;
+        assert_eq!(
+            value,
+            serde_json::json!([{"password": "abc", "score": 0, "entropy": 14.1}])
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,213 @@
+use anyhow::Result;
+use argon2::{Algorithm, Argon2, Params, Version};
+use hkdf::Hkdf;
+use sha2::{Digest, Sha256};
+
+use super::gen_pass::PasswordRules;
+
+// 参数写死，不能用库的默认值，默认值一变所有密码就都变了
+const ARGON2_MEMORY_KIB: u32 = 19 * 1024;
+const ARGON2_ITERATIONS: u32 = 2;
+const ARGON2_LANES: u32 = 1;
+const DOMAIN: &str = "rcli genpass derive v1";
+// 字符类的固定顺序，和PasswordRules里的顺序无关
+const CLASS_ORDER: [&str; 4] = ["uppercase", "lowercase", "digit", "symbol"];
+
+/// derive the password of a site and login from the master passphrase. the same inputs
+/// and rules always give the same password:
+///
+/// 1. salt = SHA-256(DOMAIN || len(site) || site || len(login) || login), lengths as u64
+///    big endian, site trimmed and lowercased
+/// 2. key = Argon2id v19 (19 MiB, 2 iterations, 1 lane, 32 bytes) of the master and salt
+/// 3. [`map_password`] of the key and counter
+pub fn derive_password(
+    master: &str,
+    site: &str,
+    login: &str,
+    counter: u32,
+    rules: &PasswordRules,
+) -> Result<String> {
+    let key = derive_key(master, site, login)?;
+    Ok(map_password(&key, counter, rules))
+}
+
+fn derive_key(master: &str, site: &str, login: &str) -> Result<[u8; 32]> {
+    if master.is_empty() {
+        anyhow::bail!("The master passphrase can't be empty");
+    }
+    let site = site.trim().to_lowercase();
+    if site.is_empty() {
+        anyhow::bail!("The site can't be empty");
+    }
+    // site和login带长度拼起来，"ab"+"c"和"a"+"bc"不会得到同样的salt
+    let mut hasher = Sha256::new();
+    hasher.update(DOMAIN);
+    for part in [site.as_str(), login] {
+        hasher.update((part.len() as u64).to_be_bytes());
+        hasher.update(part);
+    }
+    let salt = hasher.finalize();
+
+    let params = Params::new(ARGON2_MEMORY_KIB, ARGON2_ITERATIONS, ARGON2_LANES, Some(32))
+        .map_err(|e| anyhow::anyhow!("{}", e))?;
+    let mut key = [0u8; 32];
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(master.as_bytes(), &salt, &mut key)
+        .map_err(|e| anyhow::anyhow!("{}", e))?;
+    Ok(key)
+}
+
+/// turn a derived key into a password, without going through `rand` so that the result
+/// never changes with a dependency upgrade:
+///
+/// - the characters of each class are sorted by code point, classes in the order
+///   uppercase, lowercase, digit, symbol, the alphabet is all of them in that order
+/// - random numbers come from HKDF-SHA256 (no salt) of the key, block i is the 32 bytes
+///   expanded with info = DOMAIN || counter || i, both u32 big endian. the blocks are read
+///   as u32 big endian words, a number below n is word % n, rejecting words at or above
+///   the largest multiple of n
+/// - pick the minimum of each class from the class, in class order, then fill up to the
+///   length from the alphabet, then Fisher–Yates shuffle: for i from len - 1 down to 1,
+///   swap i with a number below i + 1
+pub fn map_password(key: &[u8], counter: u32, rules: &PasswordRules) -> String {
+    let mut classes: Vec<(usize, Vec<char>, usize)> = rules
+        .classes
+        .iter()
+        .map(|class| {
+            let order = CLASS_ORDER
+                .iter()
+                .position(|name| *name == class.name)
+                .unwrap_or(CLASS_ORDER.len());
+            let mut chars = class.chars.clone();
+            chars.sort_unstable();
+            chars.dedup();
+            (order, chars, class.min)
+        })
+        .collect();
+    classes.sort_by_key(|(order, _, _)| *order);
+    let alphabet: Vec<char> = classes
+        .iter()
+        .flat_map(|(_, chars, _)| chars.iter().copied())
+        .collect();
+
+    let mut stream = DeriveStream::new(key, counter);
+    let mut password = Vec::with_capacity(rules.length);
+    for (_, chars, min) in &classes {
+        for _ in 0..*min {
+            password.push(chars[stream.below(chars.len())]);
+        }
+    }
+    while password.len() < rules.length {
+        password.push(alphabet[stream.below(alphabet.len())]);
+    }
+    for i in (1..password.len()).rev() {
+        let j = stream.below(i + 1);
+        password.swap(i, j);
+    }
+    password.into_iter().collect()
+}
+
+// HKDF按块展开的随机数流
+struct DeriveStream {
+    hkdf: Hkdf<Sha256>,
+    counter: u32,
+    block: u32,
+    buf: [u8; 32],
+    pos: usize,
+}
+
+impl DeriveStream {
+    fn new(key: &[u8], counter: u32) -> Self {
+        Self {
+            hkdf: Hkdf::<Sha256>::new(None, key),
+            counter,
+            block: 0,
+            buf: [0; 32],
+            pos: 32,
+        }
+    }
+
+    fn next_u32(&mut self) -> u32 {
+        if self.pos == self.buf.len() {
+            let mut info = DOMAIN.as_bytes().to_vec();
+            info.extend_from_slice(&self.counter.to_be_bytes());
+            info.extend_from_slice(&self.block.to_be_bytes());
+            self.hkdf
+                .expand(&info, &mut self.buf)
+                .expect("32 bytes is a valid length for HKDF-SHA256");
+            self.block += 1;
+            self.pos = 0;
+        }
+        let word = u32::from_be_bytes(
+            self.buf[self.pos..self.pos + 4]
+                .try_into()
+                .expect("4 bytes won't fail"),
+        );
+        self.pos += 4;
+        word
+    }
+
+    // 拒绝采样，避免取模带来的偏差
+    fn below(&mut self, n: usize) -> usize {
+        let n = n as u64;
+        let limit = (1u64 << 32) / n * n;
+        loop {
+            let word = self.next_u32() as u64;
+            if word < limit {
+                return (word % n) as usize;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PasswordSpec;
+
+    #[test]
+    fn test_derive_password() -> Result<()> {
+        let rules = PasswordSpec::new(16).rules()?;
+        let derive = |site: &str, login: &str, counter: u32| {
+            derive_password("correct horse battery staple", site, login, counter, &rules)
+        };
+        let password = derive("example.com", "alice", 1)?;
+        assert_eq!(password, derive(" Example.COM ", "alice", 1)?);
+        assert_ne!(password, derive("example.com", "alice", 2)?);
+        assert_ne!(password, derive("example.com", "bob", 1)?);
+        assert!(derive("", "alice", 1).is_err());
+        Ok(())
+    }
+
+    // 已知答案，任何一个变了都说明所有用户的密码都变了
+    #[test]
+    fn test_derive_known_answers() -> Result<()> {
+        let key = derive_key("correct horse battery staple", "example.com", "alice")?;
+        assert_eq!(
+            hex(&key),
+            "7c77f9d4417bbc72081fc148dfee2fcbdb2b2d80fcb6ca417a1e6d583dad44e6"
+        );
+
+        let default = PasswordSpec::new(16).rules()?;
+        let no_symbol = PasswordSpec {
+            symbol: false,
+            min_digits: Some(4),
+            ..PasswordSpec::new(20)
+        }
+        .rules()?;
+        let charset = PasswordSpec {
+            charset: Some("fedcba9876543210".to_string()),
+            ..PasswordSpec::new(12)
+        }
+        .rules()?;
+        assert_eq!(map_password(&key, 1, &default), "b2Lu8Sz4@kyL@gcb");
+        assert_eq!(map_password(&key, 2, &default), "kzxw72aYpK5Vnp!T");
+        assert_eq!(map_password(&key, 1, &no_symbol), "qz2SYxGCn53CYhY2o8xu");
+        assert_eq!(map_password(&key, 1, &charset), "215408aa995e");
+        Ok(())
+    }
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+}
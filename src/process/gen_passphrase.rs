@@ -0,0 +1,172 @@
+use std::collections::HashSet;
+
+use anyhow::Result;
+use rand::{seq::SliceRandom, Rng};
+
+use crate::{cli::Capitalize, get_content};
+
+use super::gen_pass::PasswordSpec;
+
+const EFF_LARGE_WORDLIST: &str = include_str!("../../assets/eff_large_wordlist.txt");
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Passphrase {
+    pub phrase: String,
+    /// bits of entropy, assuming the attacker knows the wordlist and the options
+    pub entropy: f64,
+}
+
+/// the words of a wordlist file, or of the bundled EFF large wordlist. lines may start
+/// with diceware numbers, e.g. "11111 abacus", duplicates are removed
+pub fn load_wordlist(path: Option<&str>) -> Result<Vec<String>> {
+    let content = match path {
+        Some(path) => String::from_utf8(get_content(path)?)?,
+        None => EFF_LARGE_WORDLIST.to_string(),
+    };
+    let mut seen = HashSet::new();
+    let words: Vec<String> = content
+        .lines()
+        .filter_map(|line| line.split_whitespace().last())
+        .filter(|word| seen.insert(word.to_string()))
+        .map(String::from)
+        .collect();
+    if words.len() < 2 {
+        anyhow::bail!("The wordlist needs at least 2 distinct words");
+    }
+    Ok(words)
+}
+
+/// pick `words` random words. each inserted digit or symbol goes at the end of a random word,
+/// drawn from the digits and symbols `spec` allows
+pub fn process_passphrase(
+    wordlist: &[String],
+    words: usize,
+    separator: &str,
+    capitalize: Capitalize,
+    spec: &PasswordSpec,
+    digits: usize,
+    symbols: usize,
+) -> Result<Passphrase> {
+    if words == 0 {
+        anyhow::bail!("A passphrase needs at least one word");
+    }
+    let (digit_chars, symbol_chars) = spec.digits_and_symbols();
+    for (count, chars, name) in [
+        (digits, &digit_chars, "digits"),
+        (symbols, &symbol_chars, "symbols"),
+    ] {
+        if count > 0 && chars.is_empty() {
+            anyhow::bail!("No {} left to insert into the passphrase", name);
+        }
+    }
+    let mut rng = rand::thread_rng();
+    let mut entropy = words as f64 * (wordlist.len() as f64).log2();
+    let mut picked: Vec<String> = (0..words)
+        .map(|_| {
+            let word = wordlist
+                .choose(&mut rng)
+                .expect("wordlist won't be empty in this context");
+            capitalize.apply(word, &mut rng)
+        })
+        .collect();
+    if capitalize == Capitalize::Random {
+        entropy += words as f64;
+    }
+
+    for (count, chars) in [(digits, &digit_chars), (symbols, &symbol_chars)] {
+        for _ in 0..count {
+            let c = *chars
+                .choose(&mut rng)
+                .expect("chars won't be empty in this context");
+            let i = rng.gen_range(0..words);
+            picked[i].push(c);
+            // 字符本身加上插在哪个词后面
+            entropy += (chars.len() as f64).log2() + (words as f64).log2();
+        }
+    }
+
+    Ok(Passphrase {
+        phrase: picked.join(separator),
+        entropy,
+    })
+}
+
+impl Capitalize {
+    fn apply(&self, word: &str, rng: &mut impl Rng) -> String {
+        match self {
+            Capitalize::Lower => word.to_lowercase(),
+            Capitalize::Upper => word.to_uppercase(),
+            Capitalize::Title => title(word),
+            Capitalize::Random if rng.gen_bool(0.5) => title(word),
+            Capitalize::Random => word.to_lowercase(),
+        }
+    }
+}
+
+fn title(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first
+            .to_uppercase()
+            .chain(chars.flat_map(char::to_lowercase))
+            .collect(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_eff_passphrase() -> Result<()> {
+        let wordlist = load_wordlist(None)?;
+        assert_eq!(wordlist.len(), 7776);
+        assert_eq!(wordlist[0], "abacus");
+
+        let spec = PasswordSpec::new(16);
+        let p = process_passphrase(&wordlist, 6, " ", Capitalize::Title, &spec, 1, 1)?;
+        let words: Vec<&str> = p.phrase.split(' ').collect();
+        assert_eq!(words.len(), 6);
+        assert!(words.iter().all(|w| w.starts_with(char::is_uppercase)));
+        assert_eq!(p.phrase.chars().filter(|c| c.is_ascii_digit()).count(), 1);
+        // 6个词约77.5位，加上一个数字和一个符号
+        let expected = 6.0 * 7776f64.log2() + 3.0 + 9f64.log2() + 2.0 * 6f64.log2();
+        assert!((p.entropy - expected).abs() < 1e-9);
+
+        let spec = PasswordSpec {
+            symbol_set: Some("!#^".to_string()),
+            exclude: "^&".to_string(),
+            ..PasswordSpec::new(16)
+        };
+        for _ in 0..20 {
+            let p = process_passphrase(&wordlist, 3, "-", Capitalize::Lower, &spec, 0, 3)?;
+            let symbols: Vec<char> = p.phrase.chars().filter(|c| "!#^&*".contains(*c)).collect();
+            assert_eq!(symbols.len(), 3);
+            assert!(symbols.iter().all(|c| matches!(c, '!' | '#')));
+        }
+        let no_digits = PasswordSpec {
+            exclude: "23456789".to_string(),
+            ..PasswordSpec::new(16)
+        };
+        assert!(
+            process_passphrase(&wordlist, 3, "-", Capitalize::Lower, &no_digits, 1, 0).is_err()
+        );
+        // 插入的字符和生成密码时一样受--number、--symbol和--charset限制
+        let no_symbols = PasswordSpec {
+            symbol: false,
+            ..PasswordSpec::new(16)
+        };
+        assert!(
+            process_passphrase(&wordlist, 3, "-", Capitalize::Lower, &no_symbols, 0, 1).is_err()
+        );
+        let charset = PasswordSpec {
+            charset: Some("abc0?".to_string()),
+            ..PasswordSpec::new(16)
+        };
+        let p = process_passphrase(&wordlist, 3, " ", Capitalize::Lower, &charset, 2, 1)?;
+        assert_eq!(p.phrase.matches('0').count(), 2);
+        assert_eq!(p.phrase.matches('?').count(), 1);
+        Ok(())
+    }
+}
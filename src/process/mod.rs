@@ -1,15 +1,71 @@
 mod b64;
+mod compression;
+mod csv_binary;
 mod csv_convert;
+mod csv_describe;
+mod csv_diff;
+mod csv_encoding;
+mod csv_errors;
+mod csv_join;
+mod csv_mapping;
+mod csv_nested;
+mod csv_parallel;
+mod csv_query;
+mod csv_reader;
+mod csv_schema;
+mod csv_sort;
+mod csv_split;
+mod csv_table;
+mod csv_types;
+mod csv_writer;
+mod fake;
 mod gen_pass;
+mod gen_pass_batch;
+mod gen_pass_derive;
+mod gen_passphrase;
 mod http_serve;
 mod jwt;
+mod pass_policy;
 mod text;
 
 pub use b64::{process_decode, process_encode};
-pub use csv_convert::process_csv;
-pub use gen_pass::process_genpass;
+pub use compression::{create_output, open_input, strip_compression, Compression};
+pub use csv_binary::{binary_record_writer, infer_schema};
+pub use csv_convert::{
+    converted_records, open_records, open_records_skipping_unknown_types, process_csv,
+    value_to_cell, RecordIter,
+};
+pub use csv_describe::{describe_records, process_csv_describe, ColumnStats};
+pub use csv_diff::{diff_records, process_csv_diff, CellChange, ChangeKind, CsvDiff, RowChange};
+pub use csv_encoding::{detect_encoding, utf8_reader};
+pub use csv_errors::{to_string_record, RejectHandler, RowError};
+pub use csv_join::{process_csv_dedupe, process_csv_join};
+pub use csv_mapping::{ColumnMapper, ColumnSpec, MappingFile, Transform};
+pub use csv_nested::{flatten_record, nest_record, nested_columns};
+pub use csv_parallel::{parallel_records, ParallelRecords};
+pub use csv_query::{compare_values, process_csv_query, Query};
+pub use csv_reader::{open_csv_reader, open_flexible_csv_reader, CsvDialect};
+pub use csv_schema::{process_csv_schema, process_csv_validate, Violation};
+pub use csv_sort::{compare_records, process_csv_sort, sort_records};
+pub use csv_split::{process_csv_cat, process_csv_sample, process_csv_split, sample_records};
+pub use csv_table::{process_csv_show, Cell, TableRenderer};
+pub use csv_types::{
+    detect_date_format, infer_value, parse_bool, parse_date, parse_date_with, parse_typed,
+    ValueConverter,
+};
+pub use csv_writer::{
+    output_record_writer, output_writer, record_writer, RecordWriter, WriterConfig,
+};
+pub use fake::{process_fake, FakeColumn, FakeGenerator, FakeKind, FakeSchema};
+pub use gen_pass::{generate_password, process_genpass, CharClass, PasswordRules, PasswordSpec};
+pub use gen_pass_batch::{load_labels, write_passwords, GeneratedPassword};
+pub use gen_pass_derive::{derive_password, map_password};
+pub use gen_passphrase::{load_wordlist, process_passphrase, Passphrase};
 pub use http_serve::process_http_serve;
 pub use jwt::{proceess_jwt_sign, process_jwt_verify};
+pub use pass_policy::{
+    check_password, process_genpass_check, PasswordClass, PasswordPolicy, PasswordReport,
+};
 pub use text::{
     process_text_decrypt, process_text_encrypt, process_text_generate, process_text_sign,
     process_text_verify,
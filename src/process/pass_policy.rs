@@ -0,0 +1,331 @@
+use std::io::{BufRead, BufReader};
+
+use anyhow::Result;
+use serde::Deserialize;
+use zxcvbn::zxcvbn;
+
+use crate::{cli::Capitalize, get_reader};
+
+use super::gen_pass::{class_of, PasswordSpec};
+
+// 按策略重新生成的次数上限，超过了说明策略和长度等选项冲突
+const MAX_ATTEMPTS: usize = 1000;
+
+/// a password policy file, e.g.
+///
+/// ```toml
+/// min_length = 12
+/// max_length = 64
+/// require = ["upper", "lower", "digit"]
+/// forbidden_chars = "^&"
+/// forbidden_substrings = ["password", "juventus"]
+/// min_score = 3
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PasswordPolicy {
+    pub min_length: Option<usize>,
+    pub max_length: Option<usize>,
+    #[serde(default)]
+    pub require: Vec<PasswordClass>,
+    #[serde(default)]
+    pub forbidden_chars: String,
+    /// matched case-insensitively
+    #[serde(default)]
+    pub forbidden_substrings: Vec<String>,
+    /// minimum zxcvbn score, 0 to 4
+    pub min_score: Option<u8>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PasswordClass {
+    Upper,
+    Lower,
+    Digit,
+    Symbol,
+}
+
+/// zxcvbn's estimate of a password, and the policy rules it breaks
+#[derive(Debug, Clone, PartialEq)]
+pub struct PasswordReport {
+    pub score: u8,
+    pub guesses_log10: f64,
+    pub warning: Option<String>,
+    pub suggestions: Vec<String>,
+    /// (attack scenario, time to crack)
+    pub crack_times: Vec<(&'static str, String)>,
+    pub violations: Vec<String>,
+}
+
+impl PasswordPolicy {
+    pub fn load(path: &str) -> Result<Self> {
+        let content = String::from_utf8(crate::get_content(path)?)?;
+        let policy: Self = toml::from_str(&content)?;
+        if policy.min_score.is_some_and(|s| s > 4) {
+            anyhow::bail!("min_score must be between 0 and 4");
+        }
+        if let (Some(min), Some(max)) = (policy.min_length, policy.max_length) {
+            if min > max {
+                anyhow::bail!("min_length {} is above max_length {}", min, max);
+            }
+        }
+        Ok(policy)
+    }
+
+    /// tighten the spec so that generated passwords meet the character rules of the policy
+    pub fn apply(&self, spec: &mut PasswordSpec) {
+        if let Some(min) = self.min_length {
+            spec.length = spec.length.max(min);
+        }
+        if let Some(max) = self.max_length {
+            spec.length = spec.length.min(max);
+        }
+        for class in &self.require {
+            let (enabled, min) = match class {
+                PasswordClass::Upper => (&mut spec.upper, &mut spec.min_upper),
+                PasswordClass::Lower => (&mut spec.lower, &mut spec.min_lower),
+                PasswordClass::Digit => (&mut spec.number, &mut spec.min_digits),
+                PasswordClass::Symbol => (&mut spec.symbol, &mut spec.min_symbols),
+            };
+            *enabled = true;
+            *min = Some(min.unwrap_or(1).max(1));
+        }
+        spec.exclude.push_str(&self.forbidden_chars);
+    }
+
+    /// the passphrase counterpart of `apply`: a required digit or symbol inserts at least
+    /// one, and a policy no passphrase could meet fails here instead of after
+    /// `MAX_ATTEMPTS` tries
+    pub fn apply_passphrase(
+        &self,
+        spec: &mut PasswordSpec,
+        capitalize: Capitalize,
+        separator: &str,
+        digits: &mut usize,
+        symbols: &mut usize,
+    ) -> Result<()> {
+        self.apply(spec);
+        if let Some(c) = separator
+            .chars()
+            .find(|c| self.forbidden_chars.contains(*c))
+        {
+            anyhow::bail!("The policy forbids {:?}, which is in the separator", c);
+        }
+        let (digit_chars, symbol_chars) = spec.digits_and_symbols();
+        for class in &self.require {
+            let (count, chars, flag) = match class {
+                PasswordClass::Upper if capitalize == Capitalize::Lower => anyhow::bail!(
+                    "The policy requires an uppercase letter, use --capitalize title, upper or random"
+                ),
+                PasswordClass::Lower if capitalize == Capitalize::Upper => anyhow::bail!(
+                    "The policy requires a lowercase letter, use --capitalize lower, title or random"
+                ),
+                PasswordClass::Digit => (&mut *digits, &digit_chars, "--insert-digits"),
+                PasswordClass::Symbol => (&mut *symbols, &symbol_chars, "--insert-symbols"),
+                _ => continue,
+            };
+            if chars.is_empty() {
+                anyhow::bail!(
+                    "The policy requires a {}, but none are left for {}",
+                    class.name(),
+                    flag
+                );
+            }
+            *count = (*count).max(1);
+        }
+        Ok(())
+    }
+
+    /// the rules of the policy the password breaks
+    pub fn violations(&self, password: &str) -> Vec<String> {
+        let mut violations = Vec::new();
+        let length = password.chars().count();
+        if let Some(min) = self.min_length.filter(|min| length < *min) {
+            violations.push(format!("shorter than {} characters", min));
+        }
+        if let Some(max) = self.max_length.filter(|max| length > *max) {
+            violations.push(format!("longer than {} characters", max));
+        }
+        for class in &self.require {
+            if !password.chars().any(|c| class_of(c) == class.name()) {
+                violations.push(format!("no {} character", class.name()));
+            }
+        }
+        let forbidden: String = password
+            .chars()
+            .filter(|c| self.forbidden_chars.contains(*c))
+            .collect();
+        if !forbidden.is_empty() {
+            violations.push(format!("contains forbidden characters {:?}", forbidden));
+        }
+        let lower = password.to_lowercase();
+        for s in &self.forbidden_substrings {
+            if lower.contains(&s.to_lowercase()) {
+                violations.push(format!("contains {:?}", s));
+            }
+        }
+        if let Some(min) = self.min_score {
+            let score = u8::from(zxcvbn(password, &[]).score());
+            if score < min {
+                violations.push(format!("zxcvbn score {} is below {}", score, min));
+            }
+        }
+        violations
+    }
+
+    /// call `generate` until it returns a password that meets the policy
+    pub fn generate(&self, mut generate: impl FnMut() -> Result<String>) -> Result<String> {
+        for _ in 0..MAX_ATTEMPTS {
+            let password = generate()?;
+            if self.violations(&password).is_empty() {
+                return Ok(password);
+            }
+        }
+        anyhow::bail!(
+            "No password met the policy after {} attempts, try a longer length or fewer constraints",
+            MAX_ATTEMPTS
+        )
+    }
+}
+
+impl PasswordClass {
+    fn name(&self) -> &'static str {
+        match self {
+            PasswordClass::Upper => "uppercase",
+            PasswordClass::Lower => "lowercase",
+            PasswordClass::Digit => "digit",
+            PasswordClass::Symbol => "symbol",
+        }
+    }
+}
+
+/// estimate the strength of a password and check it against the policy
+pub fn check_password(password: &str, policy: &PasswordPolicy) -> PasswordReport {
+    let estimate = zxcvbn(password, &[]);
+    let times = estimate.crack_times();
+    PasswordReport {
+        score: u8::from(estimate.score()),
+        guesses_log10: estimate.guesses_log10(),
+        warning: estimate
+            .feedback()
+            .and_then(|f| f.warning())
+            .map(|w| w.to_string()),
+        suggestions: estimate
+            .feedback()
+            .map(|f| f.suggestions().iter().map(|s| s.to_string()).collect())
+            .unwrap_or_default(),
+        crack_times: vec![
+            (
+                "online, throttled (100/hour)",
+                times.online_throttling_100_per_hour().to_string(),
+            ),
+            (
+                "online, unthrottled (10/s)",
+                times.online_no_throttling_10_per_second().to_string(),
+            ),
+            (
+                "offline, slow hash (1e4/s)",
+                times.offline_slow_hashing_1e4_per_second().to_string(),
+            ),
+            (
+                "offline, fast hash (1e10/s)",
+                times.offline_fast_hashing_1e10_per_second().to_string(),
+            ),
+        ],
+        violations: policy.violations(password),
+    }
+}
+
+/// check every line of the input as a password
+pub fn process_genpass_check(input: &str, policy: &PasswordPolicy) -> Result<Vec<PasswordReport>> {
+    let reader = BufReader::new(get_reader(input)?);
+    let mut reports = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        // 只去掉行尾的\r，密码里的空格要保留
+        let password = line.strip_suffix('\r').unwrap_or(&line);
+        if !password.is_empty() {
+            reports.push(check_password(password, policy));
+        }
+    }
+    Ok(reports)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process::{gen_pass::generate_password, gen_passphrase::process_passphrase};
+
+    #[test]
+    fn test_policy() -> Result<()> {
+        let policy: PasswordPolicy = toml::from_str(
+            r#"
+            min_length = 12
+            require = ["upper", "digit"]
+            forbidden_chars = "^&"
+            forbidden_substrings = ["juve"]
+            min_score = 3
+            "#,
+        )?;
+        assert_eq!(
+            policy.violations("juventus"),
+            vec![
+                "shorter than 12 characters",
+                "no uppercase character",
+                "no digit character",
+                "contains \"juve\"",
+                "zxcvbn score 0 is below 3",
+            ]
+        );
+
+        let mut spec = PasswordSpec::new(8);
+        spec.symbol = false;
+        policy.apply(&mut spec);
+        assert_eq!(spec.length, 12);
+        let rules = spec.rules()?;
+        let mut rng = rand::thread_rng();
+        let password = policy.generate(|| Ok(generate_password(&rules, &mut rng)))?;
+        assert!(policy.violations(&password).is_empty());
+        assert!(!password.contains(['^', '&']));
+        Ok(())
+    }
+
+    #[test]
+    fn test_passphrase_policy() -> Result<()> {
+        let policy: PasswordPolicy = toml::from_str(
+            r#"
+            require = ["upper", "digit", "symbol"]
+            forbidden_chars = "-"
+            "#,
+        )?;
+        let wordlist = vec!["juventus".to_string(), "bianconeri".to_string()];
+        let (mut digits, mut symbols) = (0, 0);
+        let mut apply = |spec: &mut PasswordSpec, capitalize, separator: &str| {
+            policy.apply_passphrase(spec, capitalize, separator, &mut digits, &mut symbols)
+        };
+        let mut spec = PasswordSpec::new(16);
+        assert!(apply(&mut spec, Capitalize::Title, "-").is_err());
+        assert!(apply(&mut spec, Capitalize::Lower, " ").is_err());
+        let mut no_digits = PasswordSpec {
+            charset: Some("abc!".to_string()),
+            ..PasswordSpec::new(16)
+        };
+        let err = apply(&mut no_digits, Capitalize::Title, " ").unwrap_err();
+        assert!(err.to_string().contains("--insert-digits"));
+
+        let mut spec = PasswordSpec {
+            number: false,
+            ..PasswordSpec::new(16)
+        };
+        policy.apply_passphrase(&mut spec, Capitalize::Title, " ", &mut digits, &mut symbols)?;
+        assert_eq!((digits, symbols), (1, 1));
+        let phrase = policy.generate(|| {
+            let p =
+                process_passphrase(&wordlist, 3, " ", Capitalize::Title, &spec, digits, symbols)?;
+            Ok(p.phrase)
+        })?;
+        assert!(policy.violations(&phrase).is_empty());
+        Ok(())
+    }
+}